@@ -0,0 +1,57 @@
+package one.rachelt.rust_saf
+
+import android.content.Context
+import android.provider.DocumentsContract
+import androidx.test.ext.junit.runners.AndroidJUnit4
+import androidx.test.platform.app.InstrumentationRegistry
+
+import org.junit.Assert.assertNull
+import org.junit.Before
+import org.junit.Test
+import org.junit.runner.RunWith
+
+/**
+ * Runs ndk-saf against the debug-only TestDocumentsProvider. Each native test returns null on
+ * success, or the error it failed with.
+ */
+@RunWith(AndroidJUnit4::class)
+class SafInstrumentedTest {
+    companion object {
+        init {
+            System.loadLibrary("main")
+        }
+    }
+
+    private lateinit var treeUri: String
+
+    @Before
+    fun setUp() {
+        val context = InstrumentationRegistry.getInstrumentation().targetContext
+        initializeContext(context)
+        treeUri = DocumentsContract.buildTreeDocumentUri(
+            "${context.packageName}.testdocuments", TestDocumentsProvider.ROOT_ID
+        ).toString()
+    }
+
+    @Test
+    fun renameUpdatesListing() {
+        assertNull(renameUpdatesListing(treeUri))
+    }
+
+    @Test
+    fun listedDirectoryCanCreateFiles() {
+        assertNull(listedDirectoryCanCreateFiles(treeUri))
+    }
+
+    // Logs the time of listing 5000 children, and of the same listing with the per-child JNI
+    // lookups of path and URL it used to make
+    @Test
+    fun listingBenchmark() {
+        assertNull(listingBenchmark(treeUri))
+    }
+
+    private external fun initializeContext(context: Context)
+    private external fun renameUpdatesListing(uri: String): String?
+    private external fun listedDirectoryCanCreateFiles(uri: String): String?
+    private external fun listingBenchmark(uri: String): String?
+}
@@ -0,0 +1,131 @@
+package one.rachelt.rust_saf
+
+import android.database.Cursor
+import android.database.MatrixCursor
+import android.os.CancellationSignal
+import android.os.ParcelFileDescriptor
+import android.provider.DocumentsContract.Document
+import android.provider.DocumentsContract.Root
+import android.provider.DocumentsProvider
+import android.webkit.MimeTypeMap
+import java.io.File
+import java.io.FileNotFoundException
+
+// A minimal file-backed DocumentsProvider for the instrumented tests. Document IDs are paths
+// relative to the cache directory, starting with ROOT_ID, so a tree URI on ROOT_ID behaves like a
+// user-granted tree without going through the system picker. Debug builds only.
+class TestDocumentsProvider : DocumentsProvider() {
+    companion object {
+        const val ROOT_ID = "root"
+
+        private val DEFAULT_ROOT_PROJECTION = arrayOf(
+            Root.COLUMN_ROOT_ID, Root.COLUMN_DOCUMENT_ID, Root.COLUMN_TITLE, Root.COLUMN_FLAGS
+        )
+        private val DEFAULT_DOCUMENT_PROJECTION = arrayOf(
+            Document.COLUMN_DOCUMENT_ID, Document.COLUMN_DISPLAY_NAME, Document.COLUMN_SIZE,
+            Document.COLUMN_MIME_TYPE, Document.COLUMN_LAST_MODIFIED, Document.COLUMN_FLAGS
+        )
+    }
+
+    private lateinit var baseDir: File
+
+    override fun onCreate(): Boolean {
+        baseDir = File(context!!.cacheDir, "test-documents")
+        File(baseDir, ROOT_ID).mkdirs()
+        return true
+    }
+
+    private fun fileOf(documentId: String): File {
+        if (documentId != ROOT_ID && !documentId.startsWith("$ROOT_ID/")) {
+            throw FileNotFoundException("Unknown document $documentId")
+        }
+        return File(baseDir, documentId)
+    }
+
+    private fun documentIdOf(file: File): String = file.relativeTo(baseDir).path
+
+    private fun mimeTypeOf(file: File): String = when {
+        file.isDirectory -> Document.MIME_TYPE_DIR
+        else -> MimeTypeMap.getSingleton().getMimeTypeFromExtension(file.extension)
+            ?: "application/octet-stream"
+    }
+
+    private fun addRow(cursor: MatrixCursor, file: File) {
+        val flags = Document.FLAG_SUPPORTS_WRITE or Document.FLAG_SUPPORTS_DELETE or
+            Document.FLAG_SUPPORTS_RENAME or
+            (if (file.isDirectory) Document.FLAG_DIR_SUPPORTS_CREATE else 0)
+        cursor.newRow()
+            .add(Document.COLUMN_DOCUMENT_ID, documentIdOf(file))
+            .add(Document.COLUMN_DISPLAY_NAME, file.name)
+            .add(Document.COLUMN_SIZE, if (file.isDirectory) null else file.length())
+            .add(Document.COLUMN_MIME_TYPE, mimeTypeOf(file))
+            .add(Document.COLUMN_LAST_MODIFIED, file.lastModified())
+            .add(Document.COLUMN_FLAGS, flags)
+    }
+
+    override fun queryRoots(projection: Array<out String>?): Cursor {
+        val cursor = MatrixCursor(projection ?: DEFAULT_ROOT_PROJECTION)
+        cursor.newRow()
+            .add(Root.COLUMN_ROOT_ID, ROOT_ID)
+            .add(Root.COLUMN_DOCUMENT_ID, ROOT_ID)
+            .add(Root.COLUMN_TITLE, "Tests")
+            .add(Root.COLUMN_FLAGS, Root.FLAG_SUPPORTS_CREATE or Root.FLAG_SUPPORTS_IS_CHILD)
+        return cursor
+    }
+
+    override fun queryDocument(documentId: String, projection: Array<out String>?): Cursor {
+        val file = fileOf(documentId)
+        if (!file.exists()) {
+            throw FileNotFoundException("Missing document $documentId")
+        }
+        return MatrixCursor(projection ?: DEFAULT_DOCUMENT_PROJECTION).also { addRow(it, file) }
+    }
+
+    override fun queryChildDocuments(
+        parentDocumentId: String,
+        projection: Array<out String>?,
+        sortOrder: String?
+    ): Cursor {
+        val cursor = MatrixCursor(projection ?: DEFAULT_DOCUMENT_PROJECTION)
+        fileOf(parentDocumentId).listFiles()?.forEach { addRow(cursor, it) }
+        return cursor
+    }
+
+    override fun isChildDocument(parentDocumentId: String, documentId: String): Boolean =
+        documentId.startsWith("$parentDocumentId/")
+
+    override fun openDocument(
+        documentId: String,
+        mode: String,
+        signal: CancellationSignal?
+    ): ParcelFileDescriptor =
+        ParcelFileDescriptor.open(fileOf(documentId), ParcelFileDescriptor.parseMode(mode))
+
+    override fun createDocument(
+        parentDocumentId: String,
+        mimeType: String,
+        displayName: String
+    ): String {
+        val file = File(fileOf(parentDocumentId), displayName)
+        val created = if (mimeType == Document.MIME_TYPE_DIR) file.mkdir() else file.createNewFile()
+        if (!created) {
+            throw IllegalStateException("Failed to create $displayName")
+        }
+        return documentIdOf(file)
+    }
+
+    override fun renameDocument(documentId: String, displayName: String): String {
+        val file = fileOf(documentId)
+        val renamed = File(file.parentFile, displayName)
+        if (renamed.exists() || !file.renameTo(renamed)) {
+            throw IllegalStateException("Failed to rename $documentId to $displayName")
+        }
+        return documentIdOf(renamed)
+    }
+
+    override fun deleteDocument(documentId: String) {
+        if (!fileOf(documentId).deleteRecursively()) {
+            throw IllegalStateException("Failed to delete $documentId")
+        }
+    }
+}
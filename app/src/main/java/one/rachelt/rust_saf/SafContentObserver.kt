@@ -0,0 +1,21 @@
+package one.rachelt.rust_saf
+
+import android.database.ContentObserver
+
+// Forwards change notifications to the native callback of ndk-saf's AndroidFileOps::watch. The
+// handle is owned by the native side, which calls close() before releasing it.
+class SafContentObserver(private var handle: Long) : ContentObserver(null) {
+    @Synchronized
+    override fun onChange(selfChange: Boolean) {
+        if (handle != 0L) {
+            nativeOnChange(handle)
+        }
+    }
+
+    @Synchronized
+    fun close() {
+        handle = 0L
+    }
+
+    private external fun nativeOnChange(handle: Long)
+}
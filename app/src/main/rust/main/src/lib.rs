@@ -1,7 +1,7 @@
 use std::io::{Read, Write};
 use std::ops::Deref;
 use std::panic::catch_unwind;
-use std::sync::Once;
+use std::sync::{Once, OnceLock};
 use std::{ffi::c_void, panic};
 
 use log::{error, info};
@@ -11,7 +11,7 @@ use jni::{
     JavaVM,
 };
 use ndk_context::{initialize_android_context, release_android_context};
-use ndk_saf::AndroidFileOps;
+use ndk_saf::{AndroidFile, AndroidFileOps, SafError};
 use tracing_logcat::{LogcatMakeWriter, LogcatTag};
 use tracing_subscriber::fmt::format::Format;
 
@@ -20,7 +20,16 @@ const INVALID_JNI_VERSION: jint = 0;
 
 // Ensure 1-time initialization of JVM
 static INIT: Once = Once::new();
-static mut JVM: Option<*mut c_void> = None;
+static JVM: OnceLock<SendPtr> = OnceLock::new();
+
+/// Raw JavaVM pointer that can be stored in a static.
+#[derive(Clone, Copy)]
+struct SendPtr(*mut c_void);
+
+// SAFETY: The JavaVM is shared by all threads of the process and outlives the library, JNI
+// allows using its pointer from any thread.
+unsafe impl Send for SendPtr {}
+unsafe impl Sync for SendPtr {}
 
 #[allow(non_snake_case)]
 #[no_mangle]
@@ -56,12 +65,12 @@ pub extern "system" fn JNI_OnLoad(vm: *mut JavaVM, _: *mut c_void) -> jint {
     }));
     catch_unwind(|| {
         // Safely init JVM and ClassLoader
-        INIT.call_once(|| unsafe {
+        INIT.call_once(|| {
             // Convert *mut JavaVM to *mut c_void and store it
-            JVM = Some(vm as *mut c_void);
+            let _ = JVM.set(SendPtr(vm as *mut c_void));
 
             // Initialize ClassLoader for proper class finding from non-main threads
-            let java_vm = JavaVM::from_raw(vm as *mut jni::sys::JavaVM).unwrap();
+            let java_vm = unsafe { JavaVM::from_raw(vm as *mut jni::sys::JavaVM) }.unwrap();
             if let Ok(mut env) = java_vm.get_env() {
                 if let Err(e) = ndk_saf::initialize_class_loader(vm, &mut env) {
                     error!("JNI_OnLoad: Failed to setup ClassLoader: {:?}", e);
@@ -83,14 +92,12 @@ pub extern "system" fn Java_one_rachelt_rust_1saf_MainActivity_initializeContext
     _class: jni::objects::JClass,
     context: jni::objects::JObject,
 ) {
-    unsafe {
-        // Convert JObject Context to c_void pointer and initialize Context
-        if let Some(jvm) = JVM {
-            // Converting context to raw pointer
-            let context_ptr = context.into_raw() as *mut c_void;
+    // Convert JObject Context to c_void pointer and initialize Context
+    if let Some(jvm) = get_jvm() {
+        // Converting context to raw pointer
+        let context_ptr = context.into_raw() as *mut c_void;
 
-            initialize_android_context(jvm, context_ptr);
-        }
+        unsafe { initialize_android_context(jvm, context_ptr) };
     }
     info!("JNI Context initialized");
 }
@@ -113,7 +120,7 @@ pub extern "system" fn Java_one_rachelt_rust_1saf_MainActivity_releaseContext(
 }
 
 pub fn get_jvm() -> Option<*mut c_void> {
-    unsafe { JVM }
+    JVM.get().map(|jvm| jvm.0)
 }
 
 #[no_mangle]
@@ -183,3 +190,171 @@ pub extern "system" fn Java_one_rachelt_rust_1saf_MainActivity_listUriFiles(
     let remove_success = created_dir.remove_file().expect("Couldn't remove file!");
     info!("Removed file: {:?}", remove_success);
 }
+
+#[no_mangle]
+pub extern "system" fn Java_one_rachelt_rust_1saf_SafInstrumentedTest_initializeContext(
+    env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    context: jni::objects::JObject,
+) {
+    // The local reference dies with this call, keep a global one for the whole test run
+    let Ok(context) = env.new_global_ref(&context) else {
+        error!("Failed to create a global reference to the test context");
+        return;
+    };
+    if let Some(jvm) = get_jvm() {
+        unsafe { initialize_android_context(jvm, context.as_raw() as *mut c_void) };
+        std::mem::forget(context);
+    }
+}
+
+/// Run one instrumented test in a fresh directory under the tree at "uri", removing the
+/// directory afterwards. RETURNS: null on success, or the failure message for the test to report.
+fn run_device_test(
+    env: &mut jni::JNIEnv,
+    uri: jni::objects::JString,
+    name: &str,
+    test: impl FnOnce(&AndroidFile) -> Result<(), SafError>,
+) -> jni::sys::jstring {
+    let uri: String = match env.get_string(&uri) {
+        Ok(uri) => uri.into(),
+        Err(e) => return env.new_string(e.to_string()).unwrap().into_raw(),
+    };
+    let result = ndk_saf::from_tree_url(&uri)
+        .and_then(|tree| tree.create_directory(name))
+        .and_then(|dir| {
+            let result = test(&dir);
+            dir.remove_file().and(result)
+        });
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(e) => env.new_string(e.to_string()).unwrap().into_raw(),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_one_rachelt_rust_1saf_SafInstrumentedTest_renameUpdatesListing(
+    mut env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    uri: jni::objects::JString,
+) -> jni::sys::jstring {
+    run_device_test(&mut env, uri, "rename_updates_listing", |dir| {
+        let created = dir.create_file("text/plain", "before.txt")?;
+        let renamed = created.rename("after.txt")?;
+        if renamed.filename != "after.txt" {
+            return Err(SafError::Other(format!(
+                "Renamed file is called {}",
+                renamed.filename
+            )));
+        }
+        let names: Vec<String> = dir
+            .list_files()?
+            .into_iter()
+            .map(|file| file.filename)
+            .collect();
+        if names != ["after.txt"] {
+            return Err(SafError::Other(format!("Listed after rename: {:?}", names)));
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_one_rachelt_rust_1saf_SafInstrumentedTest_listedDirectoryCanCreateFiles(
+    mut env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    uri: jni::objects::JString,
+) -> jni::sys::jstring {
+    run_device_test(&mut env, uri, "listed_directory_can_create_files", |dir| {
+        dir.create_directory("child")?;
+        let child = dir
+            .list_files()?
+            .into_iter()
+            .find(|file| file.is_dir && file.filename == "child")
+            .ok_or_else(|| SafError::Other("The listing has no child directory".to_string()))?;
+        let created = child.create_file("text/plain", "inner.txt")?;
+        let names: Vec<String> = child
+            .list_files()?
+            .into_iter()
+            .map(|file| file.filename)
+            .collect();
+        if names != [created.filename] {
+            return Err(SafError::Other(format!("Listed in child: {:?}", names)));
+        }
+        Ok(())
+    })
+}
+
+/// Number of children listed by the listing benchmark.
+const BENCHMARK_CHILDREN: usize = 5000;
+
+#[no_mangle]
+pub extern "system" fn Java_one_rachelt_rust_1saf_SafInstrumentedTest_listingBenchmark(
+    mut env: jni::JNIEnv,
+    _class: jni::objects::JClass,
+    uri: jni::objects::JString,
+) -> jni::sys::jstring {
+    run_device_test(&mut env, uri, "listing_benchmark", |dir| {
+        for index in 0..BENCHMARK_CHILDREN {
+            dir.create_file("text/plain", &format!("file_{:05}.txt", index))?;
+        }
+        // Best of several runs, the first one also warms up the provider
+        let mut best = std::time::Duration::MAX;
+        let mut best_jni = std::time::Duration::MAX;
+        for _ in 0..3 {
+            let start = std::time::Instant::now();
+            let files = dir.list_files()?;
+            best = best.min(start.elapsed());
+            if files.len() != BENCHMARK_CHILDREN {
+                return Err(SafError::Other(format!(
+                    "Listed {} of {} files",
+                    files.len(),
+                    BENCHMARK_CHILDREN
+                )));
+            }
+            best_jni = best_jni.min(time_jni_path_and_url(&files)?);
+        }
+        info!(
+            "Listing benchmark: {} children in {:?} with path and URL built in Rust, {:?} with \
+             them read through android.net.Uri as before",
+            BENCHMARK_CHILDREN,
+            best,
+            best + best_jni
+        );
+        Ok(())
+    })
+}
+
+/// Time reading the path and URI string of every file through android.net.Uri, the JNI calls
+/// listings made per child before deriving both in Rust. Parsing the URIs is not timed, as
+/// listings had the Uri objects at hand.
+fn time_jni_path_and_url(files: &[AndroidFile]) -> Result<std::time::Duration, SafError> {
+    let mut env_guard = ndk_saf::get_env()?;
+    let env = &mut *env_guard;
+    env.with_local_frame(files.len() as i32 + 16, |env| {
+        let mut uris = Vec::with_capacity(files.len());
+        for file in files {
+            let url = env.new_string(&file.url)?;
+            let uri = env
+                .call_static_method(
+                    "android/net/Uri",
+                    "parse",
+                    "(Ljava/lang/String;)Landroid/net/Uri;",
+                    &[jni::objects::JValueGen::Object(&url)],
+                )?
+                .l()?;
+            env.delete_local_ref(url)?;
+            uris.push(uri);
+        }
+
+        let start = std::time::Instant::now();
+        for uri in &uris {
+            env.with_local_frame(4, |env| -> Result<(), SafError> {
+                env.call_method(uri, "getPath", "()Ljava/lang/String;", &[])?;
+                env.call_method(uri, "toString", "()Ljava/lang/String;", &[])?;
+                Ok(())
+            })?;
+        }
+        Ok(start.elapsed())
+    })
+}
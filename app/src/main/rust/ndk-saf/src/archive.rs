@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use crate::error::{Result, SafError};
+use log::info;
+use zip::{
+    read::{read_zipfile_from_stream, ZipFile},
+    write::SimpleFileOptions,
+    CompressionMethod, ZipArchive, ZipWriter,
+};
+
+use crate::file_io::COPY_BUFFER_SIZE;
+use crate::ndk_saf::{child_document, mime_type_for_name, AndroidFile, AndroidFileOps};
+
+// A writer counting the bytes passed through it
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write the subtree of "root" as a ZIP archive into "destination", see
+/// `AndroidFileOps::zip_tree_to_with_progress`. With "skip_unreadable", files that cannot be
+/// opened and directories that cannot be listed are logged and left out, see
+/// `AndroidFileOps::zip_to`. Returns the size of the archive.
+pub(crate) fn zip_tree(
+    root: &AndroidFile,
+    destination: File,
+    skip_unreadable: bool,
+    progress: &mut dyn FnMut(&str, u64) -> bool,
+) -> Result<u64> {
+    // Provider descriptors may be pipes, so write the archive as a stream with data descriptors
+    let mut zip = ZipWriter::new_stream(CountingWriter {
+        inner: destination,
+        written: 0,
+    });
+    let mut processed = 0u64;
+    zip_directory(
+        &mut zip,
+        root,
+        "",
+        skip_unreadable,
+        &mut processed,
+        progress,
+    )?;
+
+    let mut writer = zip.finish()?.into_inner();
+    writer.flush()?;
+    Ok(writer.written)
+}
+
+fn zip_directory<W: Write + io::Seek>(
+    zip: &mut ZipWriter<W>,
+    directory: &AndroidFile,
+    prefix: &str,
+    skip_unreadable: bool,
+    processed: &mut u64,
+    progress: &mut dyn FnMut(&str, u64) -> bool,
+) -> Result<()> {
+    let entries = match directory.list_files() {
+        Ok(entries) => entries,
+        Err(e) if skip_unreadable && !prefix.is_empty() => {
+            info!("Skipping the content of {}: {}", prefix, e);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let name = format!("{}{}", prefix, entry.filename);
+        if entry.is_dir {
+            let directory_name = format!("{}/", name);
+            zip.add_directory(
+                directory_name.as_str(),
+                SimpleFileOptions::default().unix_permissions(0o755),
+            )?;
+            zip_directory(
+                zip,
+                &entry,
+                &directory_name,
+                skip_unreadable,
+                processed,
+                progress,
+            )?;
+            continue;
+        }
+
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(0o644)
+            .large_file(entry.size >= u32::MAX as u64);
+        // Open the source first, so a skipped file leaves no empty entry behind
+        let mut source = match entry.open("r") {
+            Ok(source) => source,
+            Err(e) if skip_unreadable => {
+                info!("Skipping {}: {}", name, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        zip.start_file(name.as_str(), options)?;
+        let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+        loop {
+            if !progress(&name, *processed) {
+                return Err(SafError::Cancelled(format!(
+                    "Archiving was cancelled at {}",
+                    name
+                )));
+            }
+            let read = match source.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            zip.write_all(&buffer[..read])?;
+            *processed += read as u64;
+        }
+    }
+    Ok(())
+}
+
+/// Split a ZIP entry name into the path components below the extraction root, resolving "." and
+/// "..". Returns None for names that are absolute or escape the root ("Zip Slip").
+fn normalized_components(name: &str) -> Option<Vec<&str>> {
+    if name.starts_with(['/', '\\']) {
+        return None;
+    }
+    let mut components = Vec::new();
+    for component in name.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            // Drive letters of archives created on Windows
+            component if component.contains(':') => return None,
+            component => components.push(component),
+        }
+    }
+    Some(components)
+}
+
+// Extracts entries below a root directory, remembering the directories it resolved or created
+struct Extractor<'a> {
+    root: &'a AndroidFile,
+    directories: HashMap<String, AndroidFile>,
+    top_level: Vec<AndroidFile>,
+    extracted: usize,
+}
+
+impl Extractor<'_> {
+    /// Get the directory at the given components below the root, creating missing ones.
+    fn directory(&mut self, components: &[&str]) -> Result<AndroidFile> {
+        let Some((name, parents)) = components.split_last() else {
+            return Ok(self.root.clone());
+        };
+        let key = components.join("/");
+        if let Some(directory) = self.directories.get(&key) {
+            return Ok(directory.clone());
+        }
+
+        let parent = self.directory(parents)?;
+        let directory = match child_document(&parent, name)? {
+            Some(existing) if existing.is_dir => existing,
+            Some(_) => {
+                return Err(SafError::AlreadyExists(format!(
+                    "Cannot create directory {}: a file with that name exists",
+                    key
+                )))
+            }
+            None => parent.create_directory(name)?,
+        };
+        if parents.is_empty() {
+            self.top_level.push(directory.clone());
+        }
+        self.directories.insert(key, directory.clone());
+        Ok(directory)
+    }
+
+    fn extract<R: Read>(&mut self, entry: &mut ZipFile<'_, R>) -> Result<()> {
+        let components = normalized_components(entry.name()).ok_or_else(|| {
+            SafError::Other(format!(
+                "The archive entry {} escapes the target directory",
+                entry.name()
+            ))
+        })?;
+        if entry.is_dir() {
+            self.directory(&components)?;
+            return Ok(());
+        }
+        let Some((name, parents)) = components.split_last() else {
+            return Ok(());
+        };
+
+        // Existing files are overwritten, creating them again would make the provider pick a
+        // different name
+        let is_top_level = parents.is_empty();
+        let parent = self.directory(parents)?;
+        let file = match child_document(&parent, name)? {
+            Some(existing) if existing.is_dir => {
+                return Err(SafError::AlreadyExists(format!(
+                    "Cannot extract {}: a directory with that name exists",
+                    entry.name()
+                )))
+            }
+            Some(existing) => existing,
+            None => parent.create_file(&mime_type_for_name(name)?, name)?,
+        };
+        let mut destination = file.open("wt")?;
+        io::copy(entry, &mut destination)?;
+        self.extracted += 1;
+        if is_top_level && !self.top_level.iter().any(|entry| entry.url == file.url) {
+            self.top_level.push(file);
+        }
+        Ok(())
+    }
+}
+
+/// Extract the ZIP archive in "source" into "root", see `AndroidFileOps::unzip_into`. Returns
+/// the number of extracted files and the files and directories created (or overwritten)
+/// directly in "root".
+pub(crate) fn unzip_to_directory(
+    root: &AndroidFile,
+    mut source: File,
+) -> Result<(usize, Vec<AndroidFile>)> {
+    let mut extractor = Extractor {
+        root,
+        directories: HashMap::new(),
+        top_level: Vec::new(),
+        extracted: 0,
+    };
+
+    // Archives are read through their central directory when the descriptor is seekable, since
+    // entries written as a stream (with data descriptors) cannot be read from a stream again
+    if source.seek(SeekFrom::Start(0)).is_ok() {
+        let mut archive = ZipArchive::new(source)?;
+        for index in 0..archive.len() {
+            extractor.extract(&mut archive.by_index(index)?)?;
+        }
+    } else {
+        while let Some(mut entry) = read_zipfile_from_stream(&mut source)? {
+            extractor.extract(&mut entry)?;
+        }
+    }
+
+    Ok((extractor.extracted, extractor.top_level))
+}
+
+/// Extract the ZIP archive "archive" into the directory "dest_dir", like
+/// `AndroidFileOps::unzip_into`: subdirectories are created as needed, existing files are
+/// overwritten, and every new file gets the MIME type guessed from its extension. Entries with
+/// absolute paths or paths escaping "dest_dir" through ".." are rejected with an error, which
+/// stops the extraction. <br />
+/// RETURNS: The files and directories created (or overwritten) directly in "dest_dir", in
+/// archive order. <br />
+pub fn unzip_into(archive: &AndroidFile, dest_dir: &AndroidFile) -> Result<Vec<AndroidFile>> {
+    if !dest_dir.is_dir {
+        return Err(SafError::NotADirectory);
+    }
+    info!("Extracting {} into {}", archive.url, dest_dir.url);
+
+    let (_, top_level) = unzip_to_directory(dest_dir, archive.open("r")?)?;
+    Ok(top_level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_names_are_split_into_components() {
+        assert_eq!(normalized_components("a.txt"), Some(vec!["a.txt"]));
+        assert_eq!(
+            normalized_components("dir/sub/a.txt"),
+            Some(vec!["dir", "sub", "a.txt"])
+        );
+        assert_eq!(normalized_components("dir/"), Some(vec!["dir"]));
+        assert_eq!(
+            normalized_components("./dir//a.txt"),
+            Some(vec!["dir", "a.txt"])
+        );
+    }
+
+    #[test]
+    fn dot_dot_within_the_root_is_resolved() {
+        assert_eq!(normalized_components("dir/../a.txt"), Some(vec!["a.txt"]));
+        assert_eq!(
+            normalized_components("dir/sub/../../dir/a.txt"),
+            Some(vec!["dir", "a.txt"])
+        );
+    }
+
+    #[test]
+    fn names_escaping_the_root_are_rejected() {
+        for name in [
+            "../evil.txt",
+            "..",
+            "dir/../../evil.txt",
+            "./../evil.txt",
+            "..\\evil.txt",
+            "dir\\..\\..\\evil.txt",
+            "dir/..\\../evil.txt",
+        ] {
+            assert_eq!(normalized_components(name), None, "{name} was accepted");
+        }
+    }
+
+    #[test]
+    fn absolute_names_are_rejected() {
+        for name in [
+            "/etc/passwd",
+            "\\Windows\\evil.dll",
+            "//server/share/evil.txt",
+            "C:\\Windows\\evil.dll",
+            "C:/evil.txt",
+            "dir/C:evil.txt",
+        ] {
+            assert_eq!(normalized_components(name), None, "{name} was accepted");
+        }
+    }
+
+    #[test]
+    fn backslashes_separate_components() {
+        assert_eq!(
+            normalized_components("dir\\sub\\a.txt"),
+            Some(vec!["dir", "sub", "a.txt"])
+        );
+    }
+}
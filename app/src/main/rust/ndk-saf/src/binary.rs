@@ -0,0 +1,80 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    marker::PhantomData,
+};
+
+use byteorder::ReadBytesExt;
+pub use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+// A buffered reader over a document that decodes integers and floats in the byte order "B"
+#[derive(Debug)]
+pub struct BinaryReader<B: ByteOrder> {
+    reader: BufReader<File>,
+    order: PhantomData<B>,
+}
+
+// Reader of little-endian data, see `AndroidFileOps::open_le_reader`
+pub type LeReader = BinaryReader<LittleEndian>;
+// Reader of big-endian data, see `AndroidFileOps::open_be_reader`
+pub type BeReader = BinaryReader<BigEndian>;
+
+impl<B: ByteOrder> BinaryReader<B> {
+    pub(crate) fn new(file: File) -> Self {
+        BinaryReader {
+            reader: BufReader::new(file),
+            order: PhantomData,
+        }
+    }
+
+    pub fn read_u8(&mut self) -> io::Result<u8> {
+        self.reader.read_u8()
+    }
+
+    pub fn read_i8(&mut self) -> io::Result<i8> {
+        self.reader.read_i8()
+    }
+
+    pub fn read_u16(&mut self) -> io::Result<u16> {
+        self.reader.read_u16::<B>()
+    }
+
+    pub fn read_i16(&mut self) -> io::Result<i16> {
+        self.reader.read_i16::<B>()
+    }
+
+    pub fn read_u32(&mut self) -> io::Result<u32> {
+        self.reader.read_u32::<B>()
+    }
+
+    pub fn read_i32(&mut self) -> io::Result<i32> {
+        self.reader.read_i32::<B>()
+    }
+
+    pub fn read_u64(&mut self) -> io::Result<u64> {
+        self.reader.read_u64::<B>()
+    }
+
+    pub fn read_i64(&mut self) -> io::Result<i64> {
+        self.reader.read_i64::<B>()
+    }
+
+    pub fn read_f32(&mut self) -> io::Result<f32> {
+        self.reader.read_f32::<B>()
+    }
+
+    pub fn read_f64(&mut self) -> io::Result<f64> {
+        self.reader.read_f64::<B>()
+    }
+
+    /// Get back the buffered reader, e.g. to read raw bytes or switch byte order midway.
+    pub fn into_inner(self) -> BufReader<File> {
+        self.reader
+    }
+}
+
+impl<B: ByteOrder> Read for BinaryReader<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use jni::objects::GlobalRef;
+use log::info;
+
+use crate::error::{Result, SafError};
+use crate::jni_utils::get_env;
+
+// Makes long operations cancellable; wraps an android.os.CancellationSignal, which is passed to
+// provider queries, and a flag checked between rows and chunks
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>, // Set once cancel is called on the handle
+    signal: GlobalRef,          // The CancellationSignal passed to ContentResolver.query
+}
+
+// Cancels the operations using the matching CancellationToken, from any thread
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+    signal: GlobalRef,
+}
+
+/// Create a cancellation token to pass to operations such as `list_files_with_cancellation`,
+/// and the handle that cancels them, e.g. from the UI thread when the user taps "Cancel". One
+/// token can be shared by several operations; once cancelled, it stays cancelled. <br />
+/// RETURNS: The token and its handle. <br />
+pub fn new_cancellation_token() -> Result<(CancellationToken, CancelHandle)> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+
+    let signal = env.new_object("android/os/CancellationSignal", "()V", &[])?;
+    let signal = env.new_global_ref(signal)?;
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    Ok((
+        CancellationToken {
+            cancelled: cancelled.clone(),
+            signal: signal.clone(),
+        },
+        CancelHandle { cancelled, signal },
+    ))
+}
+
+impl CancellationToken {
+    /// Whether cancel was called on the handle.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Return a Cancelled error naming "operation" once the token is cancelled.
+    pub(crate) fn check(&self, operation: &str) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(SafError::Cancelled(format!("{} was cancelled", operation)));
+        }
+        Ok(())
+    }
+
+    /// The CancellationSignal to pass to provider calls.
+    pub(crate) fn signal(&self) -> &GlobalRef {
+        &self.signal
+    }
+}
+
+impl CancelHandle {
+    /// Cancel the operations using the token. Running provider queries are aborted through the
+    /// CancellationSignal; reads and copies stop before their next chunk. Calling it again has
+    /// no effect.
+    pub fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let result = get_env().and_then(|mut env_guard| {
+            env_guard
+                .call_method(self.signal.as_obj(), "cancel", "()V", &[])?
+                .v()
+        });
+        if let Err(e) = result {
+            info!("Failed to cancel the CancellationSignal: {}", e);
+        }
+    }
+}
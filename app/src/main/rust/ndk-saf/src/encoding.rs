@@ -0,0 +1,161 @@
+// Text encodings that can be told apart from a document prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+    Latin1, // Any single-byte encoding; Latin-1 is the most common one
+    Binary, // Not text at all
+}
+
+// Result of an encoding detection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodingGuess {
+    pub encoding: TextEncoding, // Most likely encoding
+    pub confidence: f32,        // Confidence between 0.0 and 1.0
+    pub has_bom: bool,          // Whether the guess comes from a byte order mark
+}
+
+impl EncodingGuess {
+    fn new(encoding: TextEncoding, confidence: f32, has_bom: bool) -> Self {
+        EncodingGuess {
+            encoding,
+            confidence,
+            has_bom,
+        }
+    }
+}
+
+// Byte order marks, longest first so UTF-32 LE is not mistaken for UTF-16 LE
+const BOMS: [(&[u8], TextEncoding); 5] = [
+    (&[0x00, 0x00, 0xFE, 0xFF], TextEncoding::Utf32Be),
+    (&[0xFF, 0xFE, 0x00, 0x00], TextEncoding::Utf32Le),
+    (&[0xEF, 0xBB, 0xBF], TextEncoding::Utf8),
+    (&[0xFE, 0xFF], TextEncoding::Utf16Be),
+    (&[0xFF, 0xFE], TextEncoding::Utf16Le),
+];
+
+/// Guess the encoding of a text document from its first bytes.
+pub(crate) fn guess_encoding(prefix: &[u8]) -> EncodingGuess {
+    if let Some((_, encoding)) = BOMS.iter().find(|(bom, _)| prefix.starts_with(bom)) {
+        return EncodingGuess::new(*encoding, 1.0, true);
+    }
+    if prefix.is_empty() {
+        return EncodingGuess::new(TextEncoding::Utf8, 0.0, false);
+    }
+
+    // UTF-16 without BOM: ASCII text leaves every other byte zero
+    let zeros_at = |parity: usize| {
+        prefix
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let pairs = prefix.len() / 2;
+    if pairs > 0 {
+        let (even, odd) = (zeros_at(0), zeros_at(1));
+        if odd * 10 > pairs * 7 && even * 10 < pairs {
+            return EncodingGuess::new(TextEncoding::Utf16Le, 0.7, false);
+        }
+        if even * 10 > pairs * 7 && odd * 10 < pairs {
+            return EncodingGuess::new(TextEncoding::Utf16Be, 0.7, false);
+        }
+    }
+
+    // Control characters other than whitespace are rare in any text encoding
+    let controls = prefix
+        .iter()
+        .filter(|b| **b < 0x20 && !matches!(**b, b'\t' | b'\n' | b'\r' | 0x0C))
+        .count();
+    if controls * 20 > prefix.len() {
+        return EncodingGuess::new(TextEncoding::Binary, 0.8, false);
+    }
+
+    match std::str::from_utf8(prefix) {
+        // Pure ASCII is valid in UTF-8 and every single-byte encoding alike
+        Ok(text) if text.is_ascii() => EncodingGuess::new(TextEncoding::Utf8, 0.6, false),
+        Ok(_) => EncodingGuess::new(TextEncoding::Utf8, 0.95, false),
+        // The prefix may end in the middle of a multi-byte sequence
+        Err(e) if e.error_len().is_none() => EncodingGuess::new(TextEncoding::Utf8, 0.9, false),
+        Err(_) => EncodingGuess::new(TextEncoding::Latin1, 0.6, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boms_are_detected_with_full_confidence() {
+        let cases: [(&[u8], TextEncoding); 5] = [
+            (b"\xEF\xBB\xBFhello", TextEncoding::Utf8),
+            (b"\xFF\xFEh\x00i\x00", TextEncoding::Utf16Le),
+            (b"\xFE\xFF\x00h\x00i", TextEncoding::Utf16Be),
+            (b"\xFF\xFE\x00\x00h\x00\x00\x00", TextEncoding::Utf32Le),
+            (b"\x00\x00\xFE\xFF\x00\x00\x00h", TextEncoding::Utf32Be),
+        ];
+        for (prefix, encoding) in cases {
+            assert_eq!(
+                guess_encoding(prefix),
+                EncodingGuess::new(encoding, 1.0, true)
+            );
+        }
+    }
+
+    #[test]
+    fn utf16_without_bom_is_detected_from_zero_bytes() {
+        let le: Vec<u8> = "plain text"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let be: Vec<u8> = "plain text"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(guess_encoding(&le).encoding, TextEncoding::Utf16Le);
+        assert_eq!(guess_encoding(&be).encoding, TextEncoding::Utf16Be);
+        assert!(!guess_encoding(&le).has_bom);
+    }
+
+    #[test]
+    fn utf8_text_is_detected() {
+        assert_eq!(
+            guess_encoding(b"plain ascii\n"),
+            EncodingGuess::new(TextEncoding::Utf8, 0.6, false)
+        );
+        assert_eq!(
+            guess_encoding("caf\u{e9} \u{97f3}\u{697d}".as_bytes()),
+            EncodingGuess::new(TextEncoding::Utf8, 0.95, false)
+        );
+        // The prefix ends in the middle of the 3-byte "音"
+        assert_eq!(
+            guess_encoding(b"abc\xE9\x9F"),
+            EncodingGuess::new(TextEncoding::Utf8, 0.9, false)
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_falls_back_to_latin1() {
+        assert_eq!(
+            guess_encoding(b"caf\xE9 cr\xE8me"),
+            EncodingGuess::new(TextEncoding::Latin1, 0.6, false)
+        );
+    }
+
+    #[test]
+    fn control_bytes_mean_binary() {
+        assert_eq!(
+            guess_encoding(b"\x7FELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00")
+                .encoding,
+            TextEncoding::Binary
+        );
+        assert_eq!(
+            guess_encoding(b""),
+            EncodingGuess::new(TextEncoding::Utf8, 0.0, false)
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::{error::Error, fmt, io};
+
+// Errors returned by the operations of this crate. Variants carrying a String hold the complete,
+// human-readable message.
+#[derive(Debug)]
+pub enum SafError {
+    NotADirectory,             // The operation needs a directory, but got a file
+    IsADirectory,              // The operation needs a file, but got a directory
+    NullDocument(String),      // The provider returned null (document, cursor, descriptor, ...)
+    PermissionDenied(String),  // The permission on the document is missing or was revoked
+    NotFound(String),          // The document or a path component does not exist
+    AlreadyExists(String),     // An entry with the requested name already exists
+    Unsupported(String),       // The provider or the Android version lacks the feature
+    NotSeekable(String),       // The provider handed out a pipe or socket instead of a file
+    Cancelled(String),         // A progress callback or a CancellationToken cancelled it
+    DeleteFailed(String),      // The provider refused to delete an existing document
+    NotInitialized(String),    // The crate was used before initialize_class_loader ran
+    QueryFailed(String),       // The provider returned no cursor for a query
+    ModeUnsupported(String),   // The provider rejected the requested open mode
+    WouldBlock(String),        // The file is locked by another holder (try_open_locked)
+    EscapesTree(String),       // A relative path leads above the root of the granted tree
+    ProviderException(String), // The provider threw a Java exception
+    Jni(jni::errors::Error),   // A JNI call failed
+    Io(io::Error),             // Reading, writing or opening a descriptor failed
+    Other(String),             // Any other failure
+    // The content ended before the size the provider reported
+    ShortRead {
+        expected: u64, // Size reported by the provider
+        got: u64,      // Bytes actually read
+    },
+    // The file is larger than the caller allowed to read into memory
+    TooLarge {
+        size: u64, // Size of the file, at least max + 1 if it was larger than reported
+        max: u64,  // The largest size allowed
+    },
+}
+
+impl fmt::Display for SafError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafError::NotADirectory => {
+                write!(f, "The provided URL does not point to a directory")
+            }
+            SafError::IsADirectory => write!(f, "The provided URL points to a directory"),
+            SafError::NullDocument(message)
+            | SafError::PermissionDenied(message)
+            | SafError::NotFound(message)
+            | SafError::AlreadyExists(message)
+            | SafError::Unsupported(message)
+            | SafError::NotSeekable(message)
+            | SafError::Cancelled(message)
+            | SafError::DeleteFailed(message)
+            | SafError::NotInitialized(message)
+            | SafError::QueryFailed(message)
+            | SafError::ModeUnsupported(message)
+            | SafError::WouldBlock(message)
+            | SafError::EscapesTree(message)
+            | SafError::ProviderException(message)
+            | SafError::Other(message) => write!(f, "{}", message),
+            SafError::ShortRead { expected, got } => {
+                write!(f, "Short read: expected {} bytes, got {}", expected, got)
+            }
+            SafError::TooLarge { size, max } => write!(
+                f,
+                "The file is too large to buffer: {} bytes, at most {} allowed",
+                size, max
+            ),
+            SafError::Jni(e) => write!(f, "JNI error: {}", e),
+            SafError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl Error for SafError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SafError::Jni(e) => Some(e),
+            SafError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<jni::errors::Error> for SafError {
+    fn from(e: jni::errors::Error) -> Self {
+        SafError::Jni(e)
+    }
+}
+
+impl From<io::Error> for SafError {
+    fn from(e: io::Error) -> Self {
+        SafError::Io(e)
+    }
+}
+
+#[cfg(feature = "zip")]
+impl From<zip::result::ZipError> for SafError {
+    fn from(e: zip::result::ZipError) -> Self {
+        match e {
+            zip::result::ZipError::Io(e) => SafError::Io(e),
+            e => SafError::Other(format!("Invalid archive: {}", e)),
+        }
+    }
+}
+
+pub(crate) type Result<T, E = SafError> = std::result::Result<T, E>;
@@ -0,0 +1,535 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem::ManuallyDrop,
+    os::fd::{AsRawFd, FromRawFd, RawFd},
+    time::{Duration, Instant},
+};
+
+use crate::cancel::CancellationToken;
+use crate::error::{Result, SafError};
+use jni::objects::GlobalRef;
+
+use crate::jni_utils::{check_exception, get_application_context, get_env};
+use crate::ndk_saf::{open_content_url, parse_uri, query_document_long, size_from_provider};
+
+// Chunk size used when streaming between descriptors in userspace
+pub(crate) const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Copy the remaining content of `source` into `destination`, returning the number of bytes
+/// copied. When both descriptors are regular files (checked with fstat), std::io::copy is used,
+/// which lets the kernel move the data with copy_file_range/sendfile without a round trip through
+/// userspace. This only helps when both documents live on local storage; pipes and sockets handed
+/// out by remote providers are streamed through a fixed-size buffer instead.
+pub(crate) fn copy_file_contents(source: &mut File, destination: &mut File) -> io::Result<u64> {
+    let both_regular =
+        source.metadata()?.file_type().is_file() && destination.metadata()?.file_type().is_file();
+    if both_regular {
+        return io::copy(source, destination);
+    }
+
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut copied = 0u64;
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        destination.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+    Ok(copied)
+}
+
+/// Copy the remaining content of `source` into `destination` like `copy_file_contents`, but
+/// always through a buffer, checking "cancellation" before every chunk. <br />
+/// RETURNS: The number of bytes copied, or a Cancelled error. <br />
+pub(crate) fn copy_file_contents_cancellable(
+    source: &mut File,
+    destination: &mut File,
+    cancellation: &CancellationToken,
+) -> Result<u64> {
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut copied = 0u64;
+    loop {
+        cancellation.check("Copying")?;
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        destination.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+    Ok(copied)
+}
+
+// A reader that copies every byte it reads into a second writer
+#[derive(Debug)]
+pub struct TeeReader<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> TeeReader<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        TeeReader { reader, writer }
+    }
+
+    /// Get back the underlying reader and writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    /// Read from the underlying reader and write the same bytes into the writer before returning
+    /// them. An error from the writer aborts the read, so the copy is never silently incomplete.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+// A reader that counts the bytes it delivers and fails at EOF if fewer bytes arrived than the
+// size the provider reported
+#[derive(Debug)]
+pub struct CheckedReader<R> {
+    reader: R,
+    expected: u64,
+    got: u64,
+}
+
+impl<R: Read> CheckedReader<R> {
+    pub(crate) fn new(reader: R, expected: u64) -> Self {
+        CheckedReader {
+            reader,
+            expected,
+            got: 0,
+        }
+    }
+
+    /// Number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.got
+    }
+
+    /// Read and discard the rest of the content, then check the total against the expected size.
+    /// RETURNS: The total number of bytes read, or a ShortRead error with both byte counts on a
+    /// short read. <br />
+    pub fn finish(mut self) -> Result<u64> {
+        self.got += io::copy(&mut self.reader, &mut io::sink())?;
+        if self.got < self.expected {
+            return Err(SafError::ShortRead {
+                expected: self.expected,
+                got: self.got,
+            });
+        }
+        Ok(self.got)
+    }
+
+    fn short_read_error(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Short read: expected {} bytes, got {}",
+                self.expected, self.got
+            ),
+        )
+    }
+}
+
+impl<R: Read> Read for CheckedReader<R> {
+    /// Read from the underlying reader. Reaching EOF before the expected number of bytes fails
+    /// with an UnexpectedEof error, on this and every later read.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if read == 0 && !buf.is_empty() && self.got < self.expected {
+            return Err(self.short_read_error());
+        }
+        self.got += read as u64;
+        Ok(read)
+    }
+}
+
+// Maximum number of times a ResilientReader reopens its document
+const MAX_REOPENS: usize = 3;
+
+// A reader that reopens its document when the provider invalidates the descriptor mid-read (e.g.
+// when a cloud provider re-syncs the file), then seeks back to where the last read ended. Resuming
+// requires a seekable descriptor: when the provider hands out a pipe, the seek fails and the
+// original error is returned instead.
+#[derive(Debug)]
+pub struct ResilientReader {
+    file: File,
+    url: String,
+    offset: u64,
+    reopens: usize,
+}
+
+impl ResilientReader {
+    pub(crate) fn new(file: File, url: String) -> Self {
+        ResilientReader {
+            file,
+            url,
+            offset: 0,
+            reopens: 0,
+        }
+    }
+
+    /// Number of times the document was reopened so far.
+    pub fn reopen_count(&self) -> usize {
+        self.reopens
+    }
+
+    // Errors returned by reads on a descriptor the provider has invalidated
+    fn is_invalidated(error: &io::Error) -> bool {
+        matches!(
+            error.raw_os_error(),
+            Some(libc::EBADF | libc::EIO | libc::ESTALE | libc::ENOTCONN)
+        )
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let mut file = open_content_url(&self.url, "r").map_err(io::Error::other)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        self.file = file;
+        self.reopens += 1;
+        Ok(())
+    }
+}
+
+impl Read for ResilientReader {
+    /// Read from the document, reopening it at most three times per reader when the descriptor
+    /// becomes invalid. If reopening or seeking back fails, the original read error is returned.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.file.read(buf) {
+                Ok(read) => {
+                    self.offset += read as u64;
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if Self::is_invalidated(&e) && self.reopens < MAX_REOPENS => {
+                    if self.reopen().is_err() {
+                        return Err(e);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// A writer that hands data to the underlying descriptor in chunks of a fixed size. At most one
+// chunk is buffered, so writing to a slow provider blocks the caller instead of accumulating the
+// whole upload in memory, and write errors surface on the write that hit them.
+#[derive(Debug)]
+pub struct ChunkedWriter {
+    file: File,
+    buffer: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl ChunkedWriter {
+    pub(crate) fn new(file: File, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        ChunkedWriter {
+            file,
+            buffer: Vec::with_capacity(chunk_size),
+            chunk_size,
+        }
+    }
+
+    /// Write out the buffered data and close the descriptor, reporting any error. Dropping the
+    /// writer flushes as well, but has to ignore errors.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Write for ChunkedWriter {
+    /// Buffer up to one chunk; once the chunk is full it is written to the descriptor before
+    /// returning. A write starting on an empty buffer with at least a full chunk of data bypasses
+    /// the buffer.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() && buf.len() >= self.chunk_size {
+            self.file.write_all(&buf[..self.chunk_size])?;
+            return Ok(self.chunk_size);
+        }
+
+        let taken = buf.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..taken]);
+        if self.buffer.len() == self.chunk_size {
+            self.flush()?;
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.file.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        self.file.flush()
+    }
+}
+
+impl Drop for ChunkedWriter {
+    fn drop(&mut self) {
+        // Errors cannot be reported here, call `finish` to observe them
+        let _ = self.flush();
+    }
+}
+
+// A writer that collects small writes and passes them to the descriptor when the buffer is full
+// or the flush interval has passed since the last flush, whichever comes first
+#[derive(Debug)]
+pub struct CoalescedWriter {
+    file: File,
+    buffer: Vec<u8>,
+    max_buffer: usize,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl CoalescedWriter {
+    pub(crate) fn new(file: File, flush_interval: Duration, max_buffer: usize) -> Self {
+        CoalescedWriter {
+            file,
+            buffer: Vec::with_capacity(max_buffer),
+            max_buffer,
+            flush_interval,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Write out the buffered data, reporting any error. Dropping the writer flushes as well,
+    /// but has to ignore errors.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Write for CoalescedWriter {
+    /// Append to the buffer, then flush it if it reached `max_buffer` bytes or the flush interval
+    /// has passed. The interval is only checked on writes, there is no background timer. Writes
+    /// larger than `max_buffer` go to the descriptor directly after flushing the buffer.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() >= self.max_buffer {
+            self.flush()?;
+            self.file.write_all(buf)?;
+            self.last_flush = Instant::now();
+            return Ok(buf.len());
+        }
+
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.max_buffer || self.last_flush.elapsed() >= self.flush_interval
+        {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.file.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        self.last_flush = Instant::now();
+        self.file.flush()
+    }
+}
+
+impl Drop for CoalescedWriter {
+    fn drop(&mut self) {
+        // Errors cannot be reported here, call `finish` to observe them
+        let _ = self.flush();
+    }
+}
+
+// A writer on a ParcelFileDescriptor that is kept attached instead of detached, so that closing
+// it runs the provider's close-time commit, see `AndroidFileOps::open_committed_writer`
+#[derive(Debug)]
+pub struct CommittedWriter {
+    // Borrowed from the ParcelFileDescriptor, which owns and closes the descriptor
+    file: ManuallyDrop<File>,
+    parcel_fd: Option<GlobalRef>,
+    url: String,
+}
+
+impl CommittedWriter {
+    /// Wrap a ParcelFileDescriptor whose descriptor "fd" (from getFd) stays owned by it.
+    pub(crate) fn new(parcel_fd: GlobalRef, fd: RawFd, url: String) -> Self {
+        CommittedWriter {
+            file: ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }),
+            parcel_fd: Some(parcel_fd),
+            url,
+        }
+    }
+
+    /// Close the ParcelFileDescriptor, letting the provider commit the written content, then
+    /// query the size the provider reports for the document. <br />
+    /// RETURNS: The committed size in bytes, or an error if the provider fails to close the
+    /// descriptor or does not report a size. <br />
+    pub fn finish(mut self) -> Result<u64> {
+        self.close()?;
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        let uri = parse_uri(env, &self.url)?;
+        let size = query_document_long(env, &context, &uri, "COLUMN_SIZE")?.ok_or_else(|| {
+            SafError::Other(format!(
+                "The provider does not report the size of {}",
+                self.url
+            ))
+        })?;
+        Ok(size_from_provider(size))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let Some(parcel_fd) = self.parcel_fd.take() else {
+            return Ok(());
+        };
+
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        // close() throws an IOException when the provider fails to commit, clear it so the
+        // thread can make JNI calls again (Drop discards the error)
+        let closed = env.call_method(&parcel_fd, "close", "()V", &[]);
+        check_exception(env)?;
+        closed?.v()?;
+        Ok(())
+    }
+}
+
+impl Write for CommittedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for CommittedWriter {
+    fn drop(&mut self) {
+        // Errors cannot be reported here, call `finish` to observe them
+        let _ = self.close();
+    }
+}
+
+// A file holding an exclusive advisory lock (flock) for as long as it is alive
+#[derive(Debug)]
+pub struct LockedFile {
+    file: File,
+}
+
+impl LockedFile {
+    /// Take an exclusive flock on the file. When "blocking" is false and another descriptor
+    /// holds a lock, a WouldBlock error is returned instead of waiting. Descriptors that cannot
+    /// be locked fail with an Unsupported error.
+    pub(crate) fn lock(file: File, blocking: bool) -> Result<Self> {
+        let operation = if blocking {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_EX | libc::LOCK_NB
+        };
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(LockedFile { file });
+            }
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                // EWOULDBLOCK equals EAGAIN on Linux
+                Some(libc::EWOULDBLOCK) => {
+                    return Err(SafError::WouldBlock(
+                        "The file is locked by another holder".to_string(),
+                    ))
+                }
+                // Pipes, sockets and some FUSE filesystems cannot be locked
+                Some(libc::EINVAL) | Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => {
+                    return Err(SafError::Unsupported(
+                        "The document descriptor does not support locking".to_string(),
+                    ))
+                }
+                _ => return Err(error.into()),
+            }
+        }
+    }
+
+    /// The locked file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl Read for LockedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for LockedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for LockedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for LockedFile {
+    fn drop(&mut self) {
+        // Closing the descriptor releases the lock as well, unlock explicitly in case it was
+        // duplicated
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_reader_finish_reports_short_read() {
+        let reader = CheckedReader::new(io::Cursor::new(vec![0u8; 3]), 5);
+        match reader.finish() {
+            Err(SafError::ShortRead { expected, got }) => assert_eq!((expected, got), (5, 3)),
+            other => panic!("expected ShortRead, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn checked_reader_finish_accepts_complete_read() {
+        let mut reader = CheckedReader::new(io::Cursor::new(vec![1u8; 5]), 5);
+        let mut first = [0u8; 2];
+        reader.read_exact(&mut first).unwrap();
+        assert_eq!(reader.finish().unwrap(), 5);
+    }
+
+    #[test]
+    fn try_lock_on_locked_file_would_block() {
+        let path = std::env::temp_dir().join(format!("ndk-saf-lock-{}", std::process::id()));
+        let _held = LockedFile::lock(File::create(&path).unwrap(), true).unwrap();
+        let contended = LockedFile::lock(File::open(&path).unwrap(), false);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(contended, Err(SafError::WouldBlock(_))));
+    }
+}
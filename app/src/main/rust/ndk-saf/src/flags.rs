@@ -0,0 +1,62 @@
+// Document capability flags, as reported in DocumentsContract.Document.COLUMN_FLAGS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct DocumentFlags(pub i32);
+
+impl DocumentFlags {
+    pub const SUPPORTS_THUMBNAIL: i32 = 1;
+    pub const SUPPORTS_WRITE: i32 = 1 << 1;
+    pub const SUPPORTS_DELETE: i32 = 1 << 2;
+    pub const DIR_SUPPORTS_CREATE: i32 = 1 << 3;
+    pub const SUPPORTS_RENAME: i32 = 1 << 6;
+    pub const SUPPORTS_COPY: i32 = 1 << 7;
+    pub const SUPPORTS_MOVE: i32 = 1 << 8;
+    pub const VIRTUAL_DOCUMENT: i32 = 1 << 9;
+    pub const SUPPORTS_REMOVE: i32 = 1 << 10;
+    pub const PARTIAL: i32 = 1 << 13;
+
+    /// Whether all bits of the given flag are set.
+    pub fn contains(&self, flag: i32) -> bool {
+        self.0 & flag == flag
+    }
+
+    pub fn supports_thumbnail(&self) -> bool {
+        self.contains(Self::SUPPORTS_THUMBNAIL)
+    }
+
+    pub fn supports_write(&self) -> bool {
+        self.contains(Self::SUPPORTS_WRITE)
+    }
+
+    pub fn supports_delete(&self) -> bool {
+        self.contains(Self::SUPPORTS_DELETE)
+    }
+
+    pub fn dir_supports_create(&self) -> bool {
+        self.contains(Self::DIR_SUPPORTS_CREATE)
+    }
+
+    pub fn supports_rename(&self) -> bool {
+        self.contains(Self::SUPPORTS_RENAME)
+    }
+
+    pub fn supports_copy(&self) -> bool {
+        self.contains(Self::SUPPORTS_COPY)
+    }
+
+    pub fn supports_move(&self) -> bool {
+        self.contains(Self::SUPPORTS_MOVE)
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        self.contains(Self::VIRTUAL_DOCUMENT)
+    }
+
+    pub fn supports_remove(&self) -> bool {
+        self.contains(Self::SUPPORTS_REMOVE)
+    }
+
+    /// The document is only partially available locally, e.g. a cloud file not downloaded yet.
+    pub fn is_partial(&self) -> bool {
+        self.contains(Self::PARTIAL)
+    }
+}
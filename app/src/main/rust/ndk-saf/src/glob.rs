@@ -0,0 +1,193 @@
+// A small glob matcher for display names, supporting `*`, `?` and character classes such as
+// `[abc]`, `[a-z]` and `[!0-9]`, with `\` escaping the next char. Matching is done on chars, not
+// bytes, and is case-sensitive.
+
+#[derive(Debug)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnySequence,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(literal) => *literal == c,
+            Token::AnyChar => true,
+            Token::AnySequence => false,
+            Token::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&c))
+                    != *negated
+            }
+        }
+    }
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                // Consecutive stars are equivalent to a single one
+                if !matches!(tokens.last(), Some(Token::AnySequence)) {
+                    tokens.push(Token::AnySequence);
+                }
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::AnyChar);
+                i += 1;
+            }
+            // A trailing backslash has nothing to escape and is matched literally
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Literal(chars[i + 1]));
+                i += 2;
+            }
+            '[' => match parse_class(&chars[i + 1..]) {
+                Some((token, consumed)) => {
+                    tokens.push(token);
+                    i += consumed + 1;
+                }
+                // An unterminated class is matched literally
+                None => {
+                    tokens.push(Token::Literal('['));
+                    i += 1;
+                }
+            },
+            c => {
+                tokens.push(Token::Literal(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Parse the inside of a character class, starting right after `[`. Returns the token and the
+/// number of chars consumed including the closing `]`.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    // A `]` right at the start is part of the class, not its end
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Check whether `name` matches the glob `pattern` in its entirety.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let tokens = tokenize(pattern);
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut t, mut n) = (0, 0);
+    // Position of the last `*` token and the name position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match tokens.get(t) {
+            Some(Token::AnySequence) => {
+                backtrack = Some((t, n));
+                t += 1;
+            }
+            Some(token) if token.matches(name[n]) => {
+                t += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more char and retry
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    t = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // Only trailing stars may remain
+    tokens[t..]
+        .iter()
+        .all(|token| matches!(token, Token::AnySequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_sequence() {
+        assert!(glob_match("*.mp3", "song.mp3"));
+        assert!(glob_match("*.mp3", ".mp3"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("*.mp3", "song.mp3.part"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        assert!(glob_match("track?.ogg", "track1.ogg"));
+        assert!(glob_match("?", "音"));
+        assert!(!glob_match("track?.ogg", "track.ogg"));
+        assert!(!glob_match("track?.ogg", "track10.ogg"));
+    }
+
+    #[test]
+    fn double_star_is_a_single_star() {
+        assert!(glob_match("**.txt", "notes.txt"));
+        assert!(glob_match("a**b", "ab"));
+        assert!(!glob_match("**.txt", "notes.md"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob_match("[abc].txt", "b.txt"));
+        assert!(glob_match("[a-z]1", "q1"));
+        assert!(!glob_match("[!0-9]x", "5x"));
+        assert!(glob_match("[]]", "]"));
+        // An unterminated class is a literal `[`
+        assert!(glob_match("[ab", "[ab"));
+    }
+
+    #[test]
+    fn backslash_escapes_wildcards() {
+        assert!(glob_match("a\\*b", "a*b"));
+        assert!(!glob_match("a\\*b", "aXb"));
+        assert!(glob_match("what\\?", "what?"));
+        assert!(!glob_match("what\\?", "whatx"));
+        assert!(glob_match("end\\", "end\\"));
+    }
+
+    #[test]
+    fn pattern_is_anchored_to_the_whole_name() {
+        assert!(!glob_match("song", "my song"));
+        assert!(!glob_match("song", "songs"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+        assert!(glob_match("*", ""));
+    }
+}
@@ -1,21 +1,88 @@
-use std::sync::{Once, RwLock};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Once, RwLock},
+};
 
 use jni::{
-    objects::{GlobalRef, JClass, JMethodID},
+    errors::JniError,
+    objects::{GlobalRef, JClass, JMethodID, JObject, JString, JValue},
+    signature::ReturnType,
     AttachGuard, JNIEnv, JavaVM,
 };
 use log::{error, info};
 
+use crate::error::SafError;
+
 // Thread-safe global state for ClassLoader caching and JavaVM storage
 static INIT: Once = Once::new();
 static CLASS_LOADER: RwLock<Option<GlobalRef>> = RwLock::new(None);
 static FIND_CLASS_METHOD: RwLock<Option<JMethodID>> = RwLock::new(None);
 static JVM: RwLock<Option<&'static JavaVM>> = RwLock::new(None);
+static APPLICATION_CONTEXT: RwLock<Option<GlobalRef>> = RwLock::new(None);
+
+// Classes tried, relative to the package, to find the app's ClassLoader when no reference class
+// is given. If none exists, the ClassLoader of the application Context is used.
+const DEFAULT_REFERENCE_CLASSES: [&str; 2] = ["MainActivity", "MainApplication"];
+
+// A JNIEnv for the current thread, returned by get_env. Dereferences to the JNIEnv.
+pub enum EnvGuard {
+    // The thread was already attached, it stays attached
+    Existing(JNIEnv<'static>),
+    // get_env attached the thread, it is detached on drop
+    Attached(AttachGuard<'static>),
+}
+
+impl EnvGuard {
+    /// Whether get_env attached the thread itself, so dropping the guard detaches it.
+    pub fn attached_here(&self) -> bool {
+        matches!(self, EnvGuard::Attached(_))
+    }
+}
+
+impl Deref for EnvGuard {
+    type Target = JNIEnv<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            EnvGuard::Existing(env) => env,
+            EnvGuard::Attached(guard) => guard,
+        }
+    }
+}
+
+impl DerefMut for EnvGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            EnvGuard::Existing(env) => env,
+            EnvGuard::Attached(guard) => guard,
+        }
+    }
+}
 
 /// Initialize the ClassLoader cache with the correct ClassLoader
 pub fn initialize_class_loader(
     vm: *mut JavaVM,
     env: &mut JNIEnv,
+) -> Result<(), jni::errors::Error> {
+    initialize_with_reference_class(vm, env, None)
+}
+
+/// Initialize the ClassLoader cache like `initialize_class_loader`, taking the ClassLoader from
+/// "reference_class", any class of the app (e.g. "com.example.app.SyncService"), instead of
+/// looking for a MainActivity. Use this in apps that have no MainActivity.
+pub fn initialize_class_loader_with_class(
+    vm: *mut JavaVM,
+    env: &mut JNIEnv,
+    reference_class: &str,
+) -> Result<(), jni::errors::Error> {
+    initialize_with_reference_class(vm, env, Some(reference_class))
+}
+
+/// Store the JavaVM and set up the ClassLoader cache, once per process.
+fn initialize_with_reference_class(
+    vm: *mut JavaVM,
+    env: &mut JNIEnv,
+    reference_class: Option<&str>,
 ) -> Result<(), jni::errors::Error> {
     INIT.call_once(|| {
         // Store the JavaVM for later use
@@ -36,7 +103,7 @@ pub fn initialize_class_loader(
         }
 
         // Setup ClassLoader for proper class finding from non-main threads
-        match setup_class_loader(env) {
+        match setup_class_loader(env, reference_class) {
             Ok((class_loader, find_class_method)) => {
                 if let (Ok(mut cl_lock), Ok(mut fcm_lock)) =
                     (CLASS_LOADER.write(), FIND_CLASS_METHOD.write())
@@ -56,8 +123,13 @@ pub fn initialize_class_loader(
     Ok(())
 }
 
-/// Setup ClassLoader during initialization to cache for later use
-fn setup_class_loader(env: &mut JNIEnv) -> Result<(GlobalRef, JMethodID), jni::errors::Error> {
+/// Setup ClassLoader during initialization to cache for later use. The ClassLoader is taken from
+/// "reference_class" if given, otherwise from the first of DEFAULT_REFERENCE_CLASSES that exists,
+/// falling back to the ClassLoader of the application Context.
+fn setup_class_loader(
+    env: &mut JNIEnv,
+    reference_class: Option<&str>,
+) -> Result<(GlobalRef, JMethodID), jni::errors::Error> {
     // Get the Activity Thread object
     let activity_thread_class = env.find_class("android/app/ActivityThread")?;
     let activity_thread = env.call_static_method(
@@ -68,44 +140,63 @@ fn setup_class_loader(env: &mut JNIEnv) -> Result<(GlobalRef, JMethodID), jni::e
     )?;
 
     // Get the Application object
-    let application = env.call_method(
-        activity_thread.l()?,
-        "getApplication",
-        "()Landroid/app/Application;",
-        &[],
-    )?;
+    let application = env
+        .call_method(
+            activity_thread.l()?,
+            "getApplication",
+            "()Landroid/app/Application;",
+            &[],
+        )?
+        .l()?;
 
     // Get the package name
-    let package_name_obj = env.call_method(
-        application.l()?,
-        "getPackageName",
-        "()Ljava/lang/String;",
-        &[],
-    )?;
+    let package_name_obj =
+        env.call_method(&application, "getPackageName", "()Ljava/lang/String;", &[])?;
     let package_name_jstring = jni::objects::JString::from(package_name_obj.l()?);
     let package_name: String = env.get_string(&package_name_jstring)?.into();
 
-    // Construct the MainActivity class name
-    let main_activity_class_name = format!("{}/MainActivity", package_name.replace('.', "/"));
-
-    // Use MainActivity as our reference class to get the correct ClassLoader
-    let main_activity_class = env.find_class(&main_activity_class_name)?;
-    let class_class = env.get_object_class(&main_activity_class)?;
     let class_loader_class = env.find_class("java/lang/ClassLoader")?;
 
-    // Get the getClassLoader method
-    let _get_class_loader_method =
-        env.get_method_id(&class_class, "getClassLoader", "()Ljava/lang/ClassLoader;")?;
-
-    // Get the ClassLoader object
-    let class_loader_obj = env.call_method(
-        &main_activity_class,
-        "getClassLoader",
-        "()Ljava/lang/ClassLoader;",
-        &[],
-    )?;
+    // Use the reference class to get the correct ClassLoader
+    let class_loader_obj = match reference_class {
+        Some(reference_class) => {
+            let class = env.find_class(reference_class.replace('.', "/"));
+            if class.is_err() {
+                env.exception_clear()?;
+            }
+            class_loader_of(env, &class?)?
+        }
+        None => {
+            let mut class_loader_obj = None;
+            for candidate in DEFAULT_REFERENCE_CLASSES {
+                let class_name = format!("{}/{}", package_name.replace('.', "/"), candidate);
+                match env.find_class(&class_name) {
+                    Ok(class) => {
+                        info!("Using {} as the reference class", class_name);
+                        class_loader_obj = Some(class_loader_of(env, &class)?);
+                        break;
+                    }
+                    // Clear the NoClassDefFoundError before trying the next candidate
+                    Err(_) => env.exception_clear()?,
+                }
+            }
+            match class_loader_obj {
+                Some(class_loader_obj) => class_loader_obj,
+                None => {
+                    info!("No reference class found, using the ClassLoader of the Context");
+                    env.call_method(
+                        &application,
+                        "getClassLoader",
+                        "()Ljava/lang/ClassLoader;",
+                        &[],
+                    )?
+                    .l()?
+                }
+            }
+        }
+    };
 
-    let class_loader = env.new_global_ref(class_loader_obj.l()?)?;
+    let class_loader = env.new_global_ref(class_loader_obj)?;
 
     // Cache the findClass method ID
     let find_class_method = env.get_method_id(
@@ -117,23 +208,38 @@ fn setup_class_loader(env: &mut JNIEnv) -> Result<(GlobalRef, JMethodID), jni::e
     Ok((class_loader, find_class_method))
 }
 
-/// Improved getEnv function that uses stored JavaVM from JNI_OnLoad
-pub fn get_env() -> Result<AttachGuard<'static>, jni::errors::Error> {
+/// Get the ClassLoader that loaded "class".
+fn class_loader_of<'local>(
+    env: &mut JNIEnv<'local>,
+    class: &JClass,
+) -> Result<JObject<'local>, jni::errors::Error> {
+    env.call_method(class, "getClassLoader", "()Ljava/lang/ClassLoader;", &[])?
+        .l()
+}
+
+/// Improved getEnv function that uses stored JavaVM from JNI_OnLoad. A thread that is already
+/// attached (e.g. the main thread, or one the host app attached) is used as is and never
+/// detached by us; other threads are attached and detached again when the guard is dropped.
+pub fn get_env() -> Result<EnvGuard, jni::errors::Error> {
     // Use the stored JavaVM from initialize_class_loader
-    let jvm_lock = JVM.read().map_err(|_| {
-        jni::errors::Error::NullPtr("Failed to acquire JavaVM read lock")
-    })?;
-    
-    let java_vm = jvm_lock.as_ref()
-        .ok_or_else(|| {
-            jni::errors::Error::NullPtr(
-                "JavaVM not initialized via JNI_OnLoad - ensure initialize_class_loader was called",
-            )
-        })?;
-    
+    let jvm_lock = JVM
+        .read()
+        .map_err(|_| jni::errors::Error::NullPtr("Failed to acquire JavaVM read lock"))?;
+
+    let java_vm = jvm_lock.as_ref().ok_or(jni::errors::Error::NullPtr(
+        "JavaVM not initialized via JNI_OnLoad - ensure initialize_class_loader was called",
+    ))?;
+
+    // Reuse the attachment of an attached thread
+    match java_vm.get_env() {
+        Ok(env) => return Ok(EnvGuard::Existing(env)),
+        Err(jni::errors::Error::JniCall(JniError::ThreadDetached)) => {}
+        Err(e) => return Err(e),
+    }
+
     // Attach current thread with error handling
     match java_vm.attach_current_thread() {
-        Ok(guard) => Ok(guard),
+        Ok(guard) => Ok(EnvGuard::Attached(guard)),
         Err(e) => {
             error!("Failed to attach current thread: {:?}", e);
             Err(e)
@@ -147,21 +253,37 @@ pub fn find_class(class_name: &str) -> Result<JClass<'_>, jni::errors::Error> {
     let env = &mut *env_guard;
 
     // Try to acquire read locks safely
-    if let (Ok(class_loader_lock), Ok(_find_class_method_lock)) =
+    if let (Ok(class_loader_lock), Ok(find_class_method_lock)) =
         (CLASS_LOADER.read(), FIND_CLASS_METHOD.read())
     {
-        if let Some(class_loader) = class_loader_lock.as_ref() {
-            let class_name_jstring = env.new_string(class_name)?;
-            let result = env.call_method(
-                class_loader.as_obj(),
-                "findClass",
-                "(Ljava/lang/String;)Ljava/lang/Class;",
-                &[(&class_name_jstring).into()],
-            )?;
-            Ok(JClass::from(result.l()?))
-        } else {
+        match (class_loader_lock.as_ref(), *find_class_method_lock) {
+            (Some(class_loader), Some(find_class_method)) => {
+                // Invoke the cached method ID, saving a method lookup on every call
+                let class_name_jstring = env.new_string(class_name)?;
+                // SAFETY: the method ID is ClassLoader.findClass(String) -> Class, resolved in
+                // setup_class_loader, and it is called on a ClassLoader with one String argument
+                let result = unsafe {
+                    env.call_method_unchecked(
+                        class_loader.as_obj(),
+                        find_class_method,
+                        ReturnType::Object,
+                        &[JValue::Object(&class_name_jstring).as_jni()],
+                    )
+                }?;
+                Ok(JClass::from(result.l()?))
+            }
+            (Some(class_loader), None) => {
+                let class_name_jstring = env.new_string(class_name)?;
+                let result = env.call_method(
+                    class_loader.as_obj(),
+                    "findClass",
+                    "(Ljava/lang/String;)Ljava/lang/Class;",
+                    &[(&class_name_jstring).into()],
+                )?;
+                Ok(JClass::from(result.l()?))
+            }
             // Fallback to standard FindClass if ClassLoader not initialized
-            env.find_class(class_name)
+            (None, _) => env.find_class(class_name),
         }
     } else {
         // Fallback to standard FindClass if locks cannot be acquired
@@ -169,6 +291,112 @@ pub fn find_class(class_name: &str) -> Result<JClass<'_>, jni::errors::Error> {
     }
 }
 
+/// Get the application Context (ActivityThread.currentActivityThread().getApplication()). It is
+/// resolved on first use and cached as a global reference, so later calls make no JNI calls.
+pub fn get_application_context() -> Result<GlobalRef, jni::errors::Error> {
+    if let Some(context) = APPLICATION_CONTEXT
+        .read()
+        .ok()
+        .and_then(|context_lock| context_lock.clone())
+    {
+        return Ok(context);
+    }
+
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+
+    let activity_thread = find_class("android/app/ActivityThread")?;
+    let current_activity_thread = env
+        .call_static_method(
+            &activity_thread,
+            "currentActivityThread",
+            "()Landroid/app/ActivityThread;",
+            &[],
+        )?
+        .l()?;
+    let application = env
+        .call_method(
+            current_activity_thread,
+            "getApplication",
+            "()Landroid/app/Application;",
+            &[],
+        )?
+        .l()?;
+    // Do not cache null, the Application does not exist yet while the process starts
+    if application.is_null() {
+        return Err(jni::errors::Error::NullPtr(
+            "The Application has not been created yet",
+        ));
+    }
+    let context = env.new_global_ref(application)?;
+
+    if let Ok(mut context_lock) = APPLICATION_CONTEXT.write() {
+        *context_lock = Some(context.clone());
+    }
+    Ok(context)
+}
+
+/// Check that the crate can be used: the JavaVM and ClassLoader must have been stored by
+/// `initialize_class_loader` (call it from JNI_OnLoad), and the application Context must be
+/// available. The public entry points that create AndroidFile objects or open URLs call this
+/// first, so using the crate too early fails with a NotInitialized error that says what is
+/// missing rather than with a null pointer deep inside a JNI call. <br />
+/// RETURNS: A NotInitialized error describing the missing initialization. <br />
+pub fn ensure_initialized() -> Result<(), SafError> {
+    if !is_class_loader_initialized() {
+        return Err(SafError::NotInitialized(
+            "ndk-saf is not initialized: call initialize_class_loader in JNI_OnLoad".to_string(),
+        ));
+    }
+    get_application_context().map_err(|e| {
+        SafError::NotInitialized(format!(
+            "ndk-saf is not initialized: the application Context is not available ({})",
+            e
+        ))
+    })?;
+    Ok(())
+}
+
+/// Check for a pending Java exception after a JNI call. If one was thrown, it is cleared and
+/// returned as an error carrying the exception class and its message, e.g.
+/// "java.lang.SecurityException: Permission Denial: ...". SecurityException maps to
+/// PermissionDenied, FileNotFoundException to NotFound, UnsupportedOperationException to
+/// Unsupported, and anything else to ProviderException.
+pub(crate) fn check_exception(env: &mut JNIEnv) -> Result<(), SafError> {
+    if !env.exception_check()? {
+        return Ok(());
+    }
+    let throwable = env.exception_occurred()?;
+    // No other JNI call is allowed while the exception is pending
+    env.exception_clear()?;
+
+    let java_string = |env: &mut JNIEnv, object: JObject| -> Result<String, SafError> {
+        if object.is_null() {
+            return Ok(String::new());
+        }
+        Ok(env.get_string(&JString::from(object))?.into())
+    };
+    let class = env
+        .call_method(&throwable, "getClass", "()Ljava/lang/Class;", &[])?
+        .l()?;
+    let class_name = env
+        .call_method(&class, "getName", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let class_name = java_string(env, class_name)?;
+    let message = env
+        .call_method(&throwable, "getMessage", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let message = format!("{}: {}", class_name, java_string(env, message)?);
+
+    Err(match class_name.as_str() {
+        "java.lang.SecurityException" => SafError::PermissionDenied(message),
+        "java.io.FileNotFoundException" => SafError::NotFound(message),
+        "java.lang.UnsupportedOperationException" => SafError::Unsupported(message),
+        "android.os.OperationCanceledException" => SafError::Cancelled(message),
+        _ => SafError::ProviderException(message),
+    })
+}
+
 /// Cleanup function for global references and JavaVM (call when library unloads)
 pub fn cleanup_class_loader() {
     // Safely acquire write locks and cleanup
@@ -182,6 +410,10 @@ pub fn cleanup_class_loader() {
         *find_class_method_lock = None;
     }
 
+    if let Ok(mut context_lock) = APPLICATION_CONTEXT.write() {
+        *context_lock = None;
+    }
+
     // Cleanup JavaVM reference (note: leaked memory won't be reclaimed)
     if let Ok(mut jvm_lock) = JVM.write() {
         *jvm_lock = None;
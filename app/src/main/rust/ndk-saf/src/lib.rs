@@ -1,9 +1,50 @@
+#[cfg(feature = "zip")]
+mod archive;
+#[cfg(feature = "binary")]
+mod binary;
+mod cancel;
+mod encoding;
+mod error;
+mod file_io;
+mod flags;
+mod glob;
 mod jni_utils;
 mod ndk_saf;
+mod observer;
+mod permissions;
+mod roots;
+mod sqlite;
+mod sync;
+mod uri;
 
+#[cfg(feature = "zip")]
+pub use archive::unzip_into;
+#[cfg(feature = "binary")]
+pub use binary::{BeReader, BigEndian, BinaryReader, ByteOrder, LeReader, LittleEndian};
+pub use cancel::{new_cancellation_token, CancelHandle, CancellationToken};
+pub use encoding::{EncodingGuess, TextEncoding};
+pub use error::SafError;
+pub use file_io::{
+    CheckedReader, ChunkedWriter, CoalescedWriter, CommittedWriter, LockedFile, ResilientReader,
+    TeeReader,
+};
+pub use flags::DocumentFlags;
 pub use jni_utils::{
-    cleanup_class_loader, find_class, get_env, initialize_class_loader, is_class_loader_initialized,
+    cleanup_class_loader, ensure_initialized, find_class, get_application_context, get_env,
+    initialize_class_loader, initialize_class_loader_with_class, is_class_loader_initialized,
+    EnvGuard,
 };
 pub use ndk_saf::{
-    from_document_file, from_tree_url, open_content_url, AndroidFile, AndroidFileOps,
+    app_external_directory, delete_all, from_document_file, from_single_uri, from_tree_parts,
+    from_tree_url, list_many, list_media, open_content_url, AndroidFile, AndroidFileOps,
+    CopyEstimate, DetailedFile, DocumentIter, ListDiff, MediaCollection, SortOrder, WalkEntry,
+};
+pub use observer::{ObserverHandle, WatchHandle};
+pub use permissions::{
+    persist_permissions, persisted_permissions, release_persistable_permission,
+    take_persistable_permission, watch_permissions, PermissionLevel, PersistedPermission,
 };
+pub use roots::QuotaInfo;
+pub use sqlite::SqliteHandle;
+pub use sync::{SyncOptions, SyncProgress, SyncReport};
+pub use uri::DocumentUri;
@@ -1,557 +1,4873 @@
 use std::{
-    fs::File,
-    os::{fd::FromRawFd, unix::io::RawFd},
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{fs::MetadataExt, io::RawFd},
+    },
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    vec,
 };
 
-use crate::jni_utils::{find_class, get_env};
-use anyhow::{anyhow, Ok, Result};
+#[cfg(feature = "zip")]
+use crate::archive::{unzip_to_directory, zip_tree};
+#[cfg(feature = "binary")]
+use crate::binary::{BeReader, LeReader};
+use crate::cancel::CancellationToken;
+use crate::encoding::{guess_encoding, EncodingGuess};
+use crate::error::{Result, SafError};
+use crate::file_io::{
+    copy_file_contents, copy_file_contents_cancellable, CheckedReader, ChunkedWriter,
+    CoalescedWriter, CommittedWriter, LockedFile, ResilientReader, TeeReader,
+};
+use crate::flags::DocumentFlags;
+use crate::glob::glob_match;
+use crate::jni_utils::{
+    check_exception, ensure_initialized, find_class, get_application_context, get_env,
+};
+use crate::observer::{watch_uri, WatchHandle};
+use crate::permissions::{owning_tree_uri, permission_level_of, PermissionLevel};
+use crate::roots::{
+    cursor_string_by_name, query_root_space, query_volume_space, QuotaInfo, RootSpace,
+};
+use crate::sqlite::{open_sqlite_path, SqliteHandle};
+use crate::sync::{sync_directory, SyncOptions, SyncReport};
+use crate::uri::{is_file_url, uri_encode, DocumentUri};
+#[cfg(feature = "compression")]
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use jni::{
-    objects::{GlobalRef, JObject, JString, JValueGen},
+    objects::{GlobalRef, JByteArray, JObject, JObjectArray, JString, JValueGen},
     JNIEnv,
 };
 use log::info;
+#[cfg(feature = "hashing")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "tokio")]
+use std::future::Future;
+
+// Number of bytes inspected by detect_encoding
+const ENCODING_PREFIX_LEN: usize = 4096;
+
+// Number of bytes read by probe_latency
+const LATENCY_PROBE_LEN: usize = 512;
+
+// Number of bytes copied by the throughput probe of estimate_copy
+const COPY_PROBE_LEN: usize = 256 * 1024;
+
+// Buffer capacity of open_buffered and create_buffered, larger than the std default of 8 KiB as
+// every refill is a system call on a provider descriptor
+const BUFFERED_CAPACITY: usize = 64 * 1024;
+
+// Size of the chunks read by sha256 and crc32
+#[cfg(feature = "hashing")]
+const HASH_CHUNK_LEN: usize = 64 * 1024;
+
+// Maximum number of worker threads used by list_many
+const LIST_MANY_WORKERS: usize = 4;
+
+// Highest file descriptor number reserved for stdio (stdin, stdout, stderr)
+const STDERR_FILENO: RawFd = 2;
 
 // Android File struct definition
 #[derive(Debug, Clone)]
 pub struct AndroidFile {
-    pub filename: String,     // File name
-    pub size: usize,          // File size in bytes, behavior undefined for directories
-    pub path: String,         // Path (not valid path, only for display)
-    pub url: String,          // Content URI (use THIS to obtain the AndroidFile object again)
-    pub is_dir: bool,         // Is the file a directory
-    document_file: GlobalRef, // JNI DocumentFile JObject representing the file
+    pub filename: String,           // File name
+    pub size: u64,                  // File size in bytes, behavior undefined for directories
+    pub path: String,               // Path (not valid path, only for display)
+    pub url: String,                // Content URI (use THIS to obtain the AndroidFile object again)
+    pub is_dir: bool,               // Is the file a directory
+    pub last_modified: Option<i64>, // Milliseconds since epoch, None if not reported
+    pub mime_type: String,          // MIME type, "vnd.android.document/directory" for dirs
+    document_id: String,            // Document ID within the provider, empty if not a document URI
+    flags: Option<DocumentFlags>,   // COLUMN_FLAGS if read from a listing cursor
+    document_file: GlobalRef,       // JNI DocumentFile JObject representing the file
 }
 
-// Android File system features
-pub trait AndroidFileOps {
-    fn open(&self, open_mode: &str) -> Result<File>;
-    fn list_files(&self) -> Result<Vec<AndroidFile>>;
-    fn create_file(&self, mime_type: &str, file_name: &str) -> Result<AndroidFile>;
-    fn create_directory(&self, dir_name: &str) -> Result<AndroidFile>;
-    fn remove_file(&self) -> Result<bool>;
+// A listed AndroidFile object together with the capability flags read in the same query; its
+// document ID, MIME type and modification time are on the AndroidFile itself
+#[derive(Debug, Clone)]
+pub struct DetailedFile {
+    pub file: AndroidFile,    // The listed file
+    pub flags: DocumentFlags, // Capabilities advertised by the provider
 }
 
-fn get_global_context(env: &mut JNIEnv) -> Result<GlobalRef> {
-    let activity_thread = find_class("android/app/ActivityThread")?;
-    let current_activity_thread = env
-        .call_static_method(
-            &activity_thread,
-            "currentActivityThread",
-            "()Landroid/app/ActivityThread;",
-            &[],
-        )?
-        .l()?;
-    let application = env
-        .call_method(
-            current_activity_thread,
-            "getApplication",
-            "()Landroid/app/Application;",
-            &[],
-        )?
-        .l()?;
-    Ok(env.new_global_ref(application)?)
+// Difference between an earlier listing of a directory and its current content
+#[derive(Debug, Clone, Default)]
+pub struct ListDiff {
+    pub added: Vec<AndroidFile>, // Entries that are new, from the current listing
+    pub removed: Vec<AndroidFile>, // Entries that are gone, from the earlier listing
+    pub changed: Vec<AndroidFile>, // Entries whose metadata changed, from the current listing
 }
 
-/// Create an AndroidFile object from a content tree URL obtained from Storage Access Framework (SAF).
-pub fn from_tree_url(url: &str) -> Result<AndroidFile> {
-    info!("Creating AndroidFile object from URL: {}", url);
-    // Obtain JNIEnv using improved get_env function
-    let mut env_guard = get_env()?;
-    let env = &mut *env_guard;
-    let context = get_global_context(env)?;
+// Size of a pending copy and the throughput measured for it, see `AndroidFileOps::estimate_copy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyEstimate {
+    pub total_bytes: u64,               // Bytes to copy
+    pub file_count: usize,              // Files to copy, directories not included
+    pub probed_throughput: Option<u64>, // Measured bytes per second, None if probing failed
+}
 
-    // Convert Rust string to Java string, and parse it as a URI
-    let url_str = env.new_string(url)?;
-    let uri = env
-        .call_static_method(
-            "android/net/Uri",
-            "parse",
-            "(Ljava/lang/String;)Landroid/net/Uri;",
-            &[JValueGen::Object(&url_str)],
-        )?
-        .l()?;
+impl CopyEstimate {
+    /// Expected duration of the copy at the probed throughput.
+    pub fn estimated_duration(&self) -> Option<Duration> {
+        let throughput = self
+            .probed_throughput
+            .filter(|throughput| *throughput > 0)?;
+        Some(Duration::from_secs_f64(
+            self.total_bytes as f64 / throughput as f64,
+        ))
+    }
+}
 
-    // Get the parent DocumentFile
-    let document_file_class = "androidx/documentfile/provider/DocumentFile";
-    let parent = env.call_static_method(
-        &document_file_class,
-        "fromTreeUri",
-        "(Landroid/content/Context;Landroid/net/Uri;)Landroidx/documentfile/provider/DocumentFile;",
-        &[JValueGen::Object(context.as_obj()), JValueGen::Object(&uri)],
-    )?.l()?;
+// An entry found by `AndroidFileOps::walk`
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub file: AndroidFile,     // The file or directory
+    pub depth: usize,          // 0 for children of the walked directory, 1 for grandchildren, ...
+    pub relative_path: String, // Path below the walked directory, components joined by "/"
+}
 
-    // Check if parent URI starts with the input URI, in which case we can use the parent directly.
-    let parent_uri = env
-        .call_method(&parent, "getUri", "()Landroid/net/Uri;", &[])?
-        .l()?;
+// Iterator over the entries of a directory, see `AndroidFileOps::iter_files`
+pub struct DocumentIter {
+    context: GlobalRef,                 // Application Context
+    parent: GlobalRef,                  // DocumentFile of the listed directory
+    parent_uri: GlobalRef,              // URI of the listed directory
+    tree_urls: TreeUrls,                // Path and URL of the tree, see `child_from_row`
+    cursor: Option<GlobalRef>,          // Children cursor, None once exhausted or closed
+    listed: vec::IntoIter<AndroidFile>, // Entries of filesystem directories, which have no cursor
+}
 
-    let parent_uri_str = env
-        .call_method(&parent_uri, "toString", "()Ljava/lang/String;", &[])?
-        .l()?;
+impl DocumentIter {
+    /// Move the cursor to the next row with a document ID and build its AndroidFile object.
+    fn read_next(&self) -> Result<Option<AndroidFile>> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
 
-    let input_uri_str = env
-        .call_method(&uri, "toString", "()Ljava/lang/String;", &[])?
-        .l()?;
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
 
-    let parent_str: String = env.get_string(&parent_uri_str.into())?.into();
-    let input_str: String = env.get_string(&input_uri_str.into())?.into();
+        while env.call_method(cursor, "moveToNext", "()Z", &[])?.z()? {
+            let file = env.with_local_frame(16, |env| -> Result<Option<AndroidFile>> {
+                let Some(row) = read_child_row(env, cursor.as_obj())? else {
+                    return Ok(None);
+                };
+                Ok(Some(child_from_row(
+                    env,
+                    &self.context,
+                    self.parent.as_obj(),
+                    self.parent_uri.as_obj(),
+                    &self.tree_urls,
+                    &row,
+                )?))
+            })?;
+            if file.is_some() {
+                return Ok(file);
+            }
+        }
+        Ok(None)
+    }
 
-    if parent_str.starts_with(&input_str) {
-        return Ok(from_document_file(&parent)?);
+    /// Close the cursor. Later calls to `next` return None.
+    fn close(&mut self) {
+        let Some(cursor) = self.cursor.take() else {
+            return;
+        };
+        let closed = get_env()
+            .and_then(|mut env_guard| env_guard.call_method(&cursor, "close", "()V", &[])?.v());
+        if let Err(e) = closed {
+            info!("Failed to close the children cursor: {}", e);
+        }
     }
+}
 
-    // Otherwise, we create a TreeDocumentFile pointing to child file.
-    let tree_document_file_class = find_class("androidx/documentfile/provider/TreeDocumentFile")?;
-    let document_file = env.new_object(
-        tree_document_file_class,
-        "(Landroidx/documentfile/provider/DocumentFile;Landroid/content/Context;Landroid/net/Uri;)V",
-        &[
-            JValueGen::Object(&parent),
-            JValueGen::Object(context.as_obj()),
-            JValueGen::Object(&uri),
-        ],
-    )?;
+impl Iterator for DocumentIter {
+    type Item = Result<AndroidFile>;
 
-    Ok(from_document_file(&document_file)?)
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(file) = self.listed.next() {
+            return Some(Ok(file));
+        }
+        match self.read_next() {
+            Ok(Some(file)) => Some(Ok(file)),
+            Ok(None) => {
+                self.close();
+                None
+            }
+            // A failed row ends the iteration, the cursor state is unknown
+            Err(e) => {
+                self.close();
+                Some(Err(e))
+            }
+        }
+    }
 }
 
-/// Create an AndroidFile object from a DocumentFile Java object.
-pub fn from_document_file(document_file: &JObject) -> Result<AndroidFile> {
-    info!(
-        "Creating AndroidFile object from DocumentFile object: {:?}",
-        document_file
-    );
-    // First, check if document_file is null
-    if document_file.is_null() {
-        return Err(anyhow!("The provided DocumentFile object is null"));
+impl Drop for DocumentIter {
+    fn drop(&mut self) {
+        self.close();
     }
+}
 
-    // Obtain JNIEnv using improved get_env function
-    let mut env_guard = get_env()?;
-    let env = &mut *env_guard;
+// Order of the entries returned by `AndroidFileOps::list_files_sorted`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    // By file name, A to Z, as `list_files`
+    #[default]
+    NameAsc,
+    // By file name, Z to A
+    NameDesc,
+    // Most recently modified first, entries without a modification time last
+    ModifiedDesc,
+    // Largest first, directories last
+    SizeDesc,
+}
 
-    // Obtain file name
-    let filename = env
-        .call_method(document_file, "getName", "()Ljava/lang/String;", &[])?
-        .l()
-        .and_then(|name| {
-            env.get_string(&JString::from(name))
-                .map(|s| s.to_string_lossy().into_owned())
-        })?;
+impl SortOrder {
+    /// The ORDER BY clause requesting this order from a provider. None for `NameAsc`, the order
+    /// of `list_files`, which queries without one like it always has.
+    fn sql(&self) -> Option<&'static str> {
+        match self {
+            SortOrder::NameAsc => None,
+            SortOrder::NameDesc => Some("_display_name DESC"),
+            SortOrder::ModifiedDesc => Some("last_modified DESC"),
+            SortOrder::SizeDesc => Some("_size DESC"),
+        }
+    }
 
-    // Obtain file size
-    let size = env.call_method(document_file, "length", "()J", &[])?.j()? as usize;
+    /// Compare two entries in this order, ties broken by `AndroidFile::sort_key`.
+    fn compare(&self, a: &AndroidFile, b: &AndroidFile) -> std::cmp::Ordering {
+        self.compare_fields(&SortFields::of(a), &SortFields::of(b))
+    }
 
-    // Obtain file path and url
-    let uri = env
-        .call_method(document_file, "getUri", "()Landroid/net/Uri;", &[])?
-        .l()?;
-    let path_object = env
-        .call_method(&uri, "getPath", "()Ljava/lang/String;", &[])?
-        .l()?;
-    let path = env
-        .get_string(&JString::from(path_object))
-        .map(|s| s.to_string_lossy().into_owned())?;
-    let url = env
-        .call_method(&uri, "toString", "()Ljava/lang/String;", &[])?
-        .l()
-        .and_then(|url| {
-            env.get_string(&JString::from(url))
-                .map(|s| s.to_string_lossy().into_owned())
-        })?;
+    fn compare_fields(&self, a: &SortFields, b: &SortFields) -> std::cmp::Ordering {
+        match self {
+            SortOrder::NameAsc => a.key().cmp(&b.key()),
+            SortOrder::NameDesc => b.key().cmp(&a.key()),
+            SortOrder::ModifiedDesc => b
+                .last_modified
+                .cmp(&a.last_modified)
+                .then_with(|| a.key().cmp(&b.key())),
+            SortOrder::SizeDesc => b.size.cmp(&a.size).then_with(|| a.key().cmp(&b.key())),
+        }
+    }
+}
 
-    // Check if the URL points to a directory
-    let is_dir = env
-        .call_method(document_file, "isDirectory", "()Z", &[])?
-        .z()
-        .unwrap_or(false);
+// The fields of an entry listings are ordered by, see SortOrder::compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SortFields<'a> {
+    filename: &'a str,
+    document_id: &'a str,
+    last_modified: Option<i64>,
+    size: Option<u64>, // None for directories
+}
 
-    // Create GlobalRef from DocumentFile object
-    let document_file_ref = env.new_global_ref(document_file)?;
+impl<'a> SortFields<'a> {
+    fn of(file: &'a AndroidFile) -> Self {
+        SortFields {
+            filename: &file.filename,
+            document_id: &file.document_id,
+            last_modified: file.last_modified,
+            size: file.file_size(),
+        }
+    }
 
-    // Construct AndroidFile struct
-    Ok(AndroidFile {
-        filename,
-        size,
-        path,
-        url,
-        is_dir,
-        document_file: document_file_ref,
-    })
+    /// Same as `AndroidFile::sort_key`.
+    fn key(&self) -> (&'a str, &'a str) {
+        (self.filename, self.document_id)
+    }
 }
 
-pub fn open_content_url(url: &str, open_mode: &str) -> Result<File> {
-    info!("Opening file url: {}, with mode: {}", url, open_mode);
+// A MediaStore collection on the shared external storage, see `list_media`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaCollection {
+    // Photos and other images, MediaStore.Images
+    Images,
+    // MediaStore.Video
+    Video,
+    // Music and other audio, MediaStore.Audio
+    Audio,
+    // MediaStore.Downloads, Android 10 (API 29) and later
+    Downloads,
+}
 
-    // Obtain JNIEnv and Context using improved get_env function
-    let mut env_guard = get_env()?;
-    let env = &mut *env_guard;
-    let context = get_global_context(env)?;
+impl MediaCollection {
+    /// The MediaStore class holding the EXTERNAL_CONTENT_URI of the collection.
+    fn class(&self) -> &'static str {
+        match self {
+            MediaCollection::Images => "android/provider/MediaStore$Images$Media",
+            MediaCollection::Video => "android/provider/MediaStore$Video$Media",
+            MediaCollection::Audio => "android/provider/MediaStore$Audio$Media",
+            MediaCollection::Downloads => "android/provider/MediaStore$Downloads",
+        }
+    }
+}
 
-    // Get ContentResolver object from Context
-    let content_resolver = env
+// MediaStore.MediaColumns read by `list_media`, in cursor index order
+const MEDIA_COLUMNS: [&str; 5] = [
+    "_id",
+    "_display_name",
+    "_size",
+    "mime_type",
+    "date_modified",
+];
+
+// Android File system features
+pub trait AndroidFileOps {
+    fn open(&self, open_mode: &str) -> Result<File>;
+    fn open_buffered(&self, open_mode: &str) -> Result<BufReader<File>>;
+    fn create_buffered(&self, mime_type: &str, file_name: &str) -> Result<BufWriter<File>>;
+    fn list_files(&self) -> Result<Vec<AndroidFile>>;
+    fn list_files_sorted(&self, order: SortOrder) -> Result<Vec<AndroidFile>>;
+    fn list_files_with_cancellation(
+        &self,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<AndroidFile>>;
+    fn refresh(&self) -> Result<AndroidFile>;
+    fn watch<F: Fn() + Send + 'static>(&self, cb: F) -> Result<WatchHandle>;
+    fn create_file(&self, mime_type: &str, file_name: &str) -> Result<AndroidFile>;
+    fn create_directory(&self, dir_name: &str) -> Result<AndroidFile>;
+    fn remove_file(&self) -> Result<bool>;
+    fn delete(&self) -> Result<()>;
+    fn delete_recursive(&self) -> Result<usize>;
+    fn document_url(&self) -> Result<String>;
+    fn find(&self, pattern: &str, recursive: bool) -> Result<Vec<AndroidFile>>;
+    fn create_file_from(
+        &self,
+        mime_type: &str,
+        file_name: &str,
+        source: &mut File,
+    ) -> Result<AndroidFile>;
+    fn import_from_path(&self, src: &Path, mime_type: &str, new_name: &str) -> Result<AndroidFile>;
+    fn export_to_path(&self, dest: &Path) -> Result<u64>;
+    fn read_prefix(&self, len: usize) -> Result<Vec<u8>>;
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>>;
+    fn is_seekable(&self) -> Result<bool>;
+    fn read_chunks<F: FnMut(&[u8]) -> Result<()>>(&self, chunk_size: usize, cb: F) -> Result<u64>;
+    fn detect_encoding(&self) -> Result<EncodingGuess>;
+    fn create_file_exclusive(&self, mime_type: &str, file_name: &str) -> Result<AndroidFile>;
+    fn probe_latency(&self) -> Result<Duration>;
+    fn list_files_detailed(&self) -> Result<Vec<DetailedFile>>;
+    fn move_to_trash(&self) -> Result<()>;
+    fn restore_from_trash(&self) -> Result<()>;
+    fn navigate(&self, relative_path: &str) -> Result<AndroidFile>;
+    fn parent(&self) -> Result<Option<AndroidFile>>;
+    fn thumbnail(&self, width: i32, height: i32) -> Result<Vec<u8>>;
+    fn open_as(&self, mime_type: &str) -> Result<File>;
+    fn stream_types(&self) -> Result<Vec<String>>;
+    #[cfg(feature = "compression")]
+    fn open_gz_reader(&self) -> Result<GzDecoder<File>>;
+    #[cfg(feature = "compression")]
+    fn create_gz_file(&self, file_name: &str, data: &[u8]) -> Result<AndroidFile>;
+    fn owning_tree_url(&self) -> Result<String>;
+    fn permission_level(&self) -> Result<PermissionLevel>;
+    fn open_tee<W: Write>(&self, cache_writer: W) -> Result<TeeReader<File, W>>;
+    fn provider_quota(&self) -> Result<Option<QuotaInfo>>;
+    fn available_bytes(&self) -> Result<Option<u64>>;
+    fn total_bytes(&self) -> Result<Option<u64>>;
+    fn filesystem_path(&self) -> Result<Option<PathBuf>>;
+    fn open_sqlite(&self) -> Result<SqliteHandle>;
+    fn relative_path_from(&self, ancestor: &AndroidFile) -> Result<Option<String>>;
+    fn open_chunked_writer(&self, chunk_size: usize) -> Result<ChunkedWriter>;
+    fn is_placeholder(&self) -> Result<bool>;
+    fn open_committed_writer(&self) -> Result<CommittedWriter>;
+    fn open_checked_read(&self) -> Result<CheckedReader<File>>;
+    fn rename_keep_extension(&self, new_stem: &str) -> Result<AndroidFile>;
+    fn list_diff(&self, previous: &[AndroidFile]) -> Result<ListDiff>;
+    fn open_locked(&self, open_mode: &str) -> Result<LockedFile>;
+    fn try_open_locked(&self, open_mode: &str) -> Result<LockedFile>;
+    fn list_with_name_like(&self, pattern: &str) -> Result<Vec<AndroidFile>>;
+    fn list_files_filtered(&self, mime_prefix: &str) -> Result<Vec<AndroidFile>>;
+    fn open_cursor(&self, max_size: usize) -> Result<Cursor<Vec<u8>>>;
+    fn sync_into(&self, local_dir: &Path, options: SyncOptions) -> Result<SyncReport>;
+    fn verified(&self) -> Result<AndroidFile>;
+    fn query_column(&self, column: &str) -> Result<Option<String>>;
+    fn open_coalesced_writer(
+        &self,
+        flush_interval: Duration,
+        max_buffer: usize,
+    ) -> Result<CoalescedWriter>;
+    fn authority(&self) -> Result<String>;
+    fn tree_document_id(&self) -> Result<Option<String>>;
+    fn estimate_copy(&self, dest_dir: &AndroidFile) -> Result<CopyEstimate>;
+    fn open_resilient_read(&self) -> Result<ResilientReader>;
+    fn truncate(&self, len: u64) -> Result<()>;
+    fn for_each_child<F: FnMut(AndroidFile) -> Result<()>>(&self, f: F) -> Result<()>;
+    fn iter_files(&self) -> Result<DocumentIter>;
+    fn preview_created_name(&self, mime_type: &str, file_name: &str) -> Result<String>;
+    fn rename(&self, new_name: &str) -> Result<AndroidFile>;
+    fn copy_to(&self, dest_dir: &AndroidFile, new_name: Option<&str>) -> Result<AndroidFile>;
+    fn copy_to_with_cancellation(
+        &self,
+        dest_dir: &AndroidFile,
+        new_name: Option<&str>,
+        cancellation: &CancellationToken,
+    ) -> Result<AndroidFile>;
+    fn move_to(&self, dest_dir: &AndroidFile) -> Result<AndroidFile>;
+    fn walk(&self) -> Result<Vec<WalkEntry>>;
+    fn walk_with_cancellation(&self, cancellation: &CancellationToken) -> Result<Vec<WalkEntry>>;
+    fn exists(&self) -> Result<bool>;
+    fn find_file(&self, name: &str) -> Result<Option<AndroidFile>>;
+    fn create_directories(&self, relative_path: &str) -> Result<AndroidFile>;
+    fn read_to_bytes(&self) -> Result<Vec<u8>>;
+    fn lines(&self) -> Result<impl Iterator<Item = Result<String>>>;
+    fn write_bytes(&self, data: &[u8]) -> Result<()>;
+    fn open_append(&self) -> Result<File>;
+    fn append_bytes(&self, data: &[u8]) -> Result<()>;
+    #[cfg(feature = "binary")]
+    fn open_le_reader(&self) -> Result<LeReader>;
+    #[cfg(feature = "binary")]
+    fn open_be_reader(&self) -> Result<BeReader>;
+    #[cfg(feature = "zip")]
+    fn zip_tree_to(&self, dest_file: &AndroidFile) -> Result<u64>;
+    #[cfg(feature = "zip")]
+    fn zip_tree_to_with_progress(
+        &self,
+        dest_file: &AndroidFile,
+        progress: &mut dyn FnMut(&str, u64) -> bool,
+    ) -> Result<u64>;
+    #[cfg(feature = "zip")]
+    fn zip_to(&self, dest: &AndroidFile) -> Result<u64>;
+    #[cfg(feature = "zip")]
+    fn unzip_into(&self, archive: &AndroidFile) -> Result<usize>;
+    #[cfg(feature = "hashing")]
+    fn sha256(&self) -> Result<[u8; 32]>;
+    #[cfg(feature = "hashing")]
+    fn crc32(&self) -> Result<u32>;
+    #[cfg(feature = "tokio")]
+    fn open_async(&self, open_mode: &str) -> impl Future<Output = Result<tokio::fs::File>> + Send;
+}
+
+/// Get the ContentResolver of the given Context.
+pub(crate) fn content_resolver<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &GlobalRef,
+) -> Result<JObject<'local>> {
+    Ok(env
         .call_method(
-            context,
+            context.as_obj(),
             "getContentResolver",
             "()Landroid/content/ContentResolver;",
             &[],
         )?
-        .l()?;
+        .l()?)
+}
 
-    // Convert URI string to Java Uri object, open mode to Java string
+/// Parse a URI string into an android.net.Uri object.
+pub(crate) fn parse_uri<'local>(env: &mut JNIEnv<'local>, url: &str) -> Result<JObject<'local>> {
     let url_str = env.new_string(url)?;
-    let uri = env
+    Ok(env
         .call_static_method(
             "android/net/Uri",
             "parse",
             "(Ljava/lang/String;)Landroid/net/Uri;",
             &[JValueGen::Object(&url_str)],
         )?
-        .l()?;
-    let mode_str = env.new_string(open_mode)?;
-
-    // Open the file descriptor and detach it
-    let parcel_fd = env
-        .call_method(
-            content_resolver,
-            "openFileDescriptor",
-            "(Landroid/net/Uri;Ljava/lang/String;)Landroid/os/ParcelFileDescriptor;",
-            &[JValueGen::Object(&uri), JValueGen::Object(&mode_str)],
-        )?
-        .l()?;
-    let fd = env.call_method(parcel_fd, "detachFd", "()I", &[])?.i()? as RawFd;
-
-    // Validate file descriptor before creating File object
-    if fd < 0 {
-        return Err(anyhow!("Invalid file descriptor: {}", fd));
-    }
-
-    // Create a new file from the validated file descriptor
-    let file = unsafe { File::from_raw_fd(fd) };
-    Ok(file)
+        .l()?)
 }
 
-impl AndroidFileOps for AndroidFile {
-    /// Open the file represented by the AndroidFile object with the specified open mode.
-    /// The "open_mode" str corresponds to that in Android ContentResolver.openFileDescriptor method,
-    /// which can be "r", "w", "wt", "wa", "rw" or "rwt". Please note that there is <b>no</b> standard
-    /// behavior for each mode, and the behavior may vary among different Android versions and file
-    /// providers. For example, "w" may truncate the file or not, so it is recommended to specify the
-    /// mode explicitly.
-    /// Furthermore, "rw" mode requires an on-disk file that supports seeking, while "r" mode and "w"
-    /// mode can be used to read or write to a pipe or socket, respectively.
-    fn open(&self, open_mode: &str) -> Result<File> {
-        // No, you would not want to use this method to open a directory
-        if self.is_dir {
-            return Err(anyhow!("The provided URL points to a directory"));
-        }
-
-        open_content_url(&self.url, open_mode)
-    }
+/// Convert a java.lang.String object into a Rust string.
+pub(crate) fn jstring_to_string(env: &mut JNIEnv, string: JObject) -> Result<String> {
+    Ok(env
+        .get_string(&JString::from(string))?
+        .to_string_lossy()
+        .into_owned())
+}
 
-    /// List files in the directory represented by the AndroidFile object. If the object does not
-    /// represent a tree directory, an error will be returned.
-    fn list_files(&self) -> Result<Vec<AndroidFile>> {
-        // Check if the DocumentFile object represents a directory
-        if !self.is_dir {
-            return Err(anyhow!("The provided URL does not point to a directory"));
-        }
-        info!("Listing files in directory: {}", self.url);
+/// Call toString() on an object (usually an android.net.Uri) and convert the result.
+pub(crate) fn object_to_string(env: &mut JNIEnv, object: &JObject) -> Result<String> {
+    let string = env
+        .call_method(object, "toString", "()Ljava/lang/String;", &[])?
+        .l()?;
+    jstring_to_string(env, string)
+}
 
-        // Obtain JNIEnv using improved get_env function
-        let mut env_guard = get_env()?;
-        let env = &mut *env_guard;
-        let context = get_global_context(env)?;
+/// Get the document ID of a document URI. Bare tree URIs, which have no document part, resolve
+/// to the ID of the tree root.
+pub(crate) fn document_id_of(env: &mut JNIEnv, uri: &JObject) -> Result<String> {
+    let documents_contract_class = "android/provider/DocumentsContract";
+    let document_id = env.call_static_method(
+        documents_contract_class,
+        "getDocumentId",
+        "(Landroid/net/Uri;)Ljava/lang/String;",
+        &[JValueGen::Object(uri)],
+    );
+    let document_id = if document_id.is_err() {
+        // getDocumentId throws IllegalArgumentException for tree URIs without a document part
+        env.exception_clear()?;
+        env.call_static_method(
+            documents_contract_class,
+            "getTreeDocumentId",
+            "(Landroid/net/Uri;)Ljava/lang/String;",
+            &[JValueGen::Object(uri)],
+        )?
+    } else {
+        document_id?
+    };
+    jstring_to_string(env, document_id.l()?)
+}
 
-        // Get ContentResolver
-        let content_resolver = env
-            .call_method(
-                context.as_obj(),
-                "getContentResolver",
-                "()Landroid/content/ContentResolver;",
-                &[],
-            )?
-            .l()?;
+/// Get the display path and the full URI string of an android.net.Uri object.
+fn uri_path_and_url(env: &mut JNIEnv, uri: &JObject) -> Result<(String, String)> {
+    let path_object = env
+        .call_method(uri, "getPath", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let path = jstring_to_string(env, path_object)?;
+    let url = object_to_string(env, uri)?;
+    Ok((path, url))
+}
 
-        // Parse parent URI from self.url
-        let parent_uri_str = env.new_string(&self.url)?;
-        let parent_uri = env
-            .call_static_method(
-                "android/net/Uri",
-                "parse",
-                "(Ljava/lang/String;)Landroid/net/Uri;",
-                &[JValueGen::Object(&parent_uri_str)],
-            )?
-            .l()?;
+// Display path and URI string of the tree a listed directory belongs to, from which the path
+// and URI of every child are derived without JNI calls
+#[derive(Default)]
+struct TreeUrls {
+    path: String, // e.g. "/tree/primary:Music"
+    url: String,  // e.g. "content://com.android.externalstorage.documents/tree/primary%3AMusic"
+}
 
+impl TreeUrls {
+    /// Get the urls of the tree of "parent_uri", a tree URI with or without a document part.
+    fn of(env: &mut JNIEnv, parent_uri: &JObject) -> Result<Self> {
         let documents_contract_class = "android/provider/DocumentsContract";
-        // Get document ID of parent URI
-        let parent_document_id = env
+        let authority = env
+            .call_method(parent_uri, "getAuthority", "()Ljava/lang/String;", &[])?
+            .l()?;
+        let tree_document_id = env
             .call_static_method(
                 documents_contract_class,
-                "getDocumentId",
+                "getTreeDocumentId",
                 "(Landroid/net/Uri;)Ljava/lang/String;",
-                &[JValueGen::Object(&parent_uri)],
+                &[JValueGen::Object(parent_uri)],
             )?
             .l()?;
-
-        // Build children URI
-        let children_uri = env
+        let tree_uri = env
             .call_static_method(
                 documents_contract_class,
-                "buildChildDocumentsUriUsingTree",
-                "(Landroid/net/Uri;Ljava/lang/String;)Landroid/net/Uri;",
+                "buildTreeDocumentUri",
+                "(Ljava/lang/String;Ljava/lang/String;)Landroid/net/Uri;",
                 &[
-                    JValueGen::Object(&parent_uri),
-                    JValueGen::Object(&parent_document_id),
+                    JValueGen::Object(&authority),
+                    JValueGen::Object(&tree_document_id),
                 ],
             )?
             .l()?;
+        let (path, url) = uri_path_and_url(env, &tree_uri)?;
+        Ok(TreeUrls { path, url })
+    }
 
-        // Define projection
-        let document_class = "android/provider/DocumentsContract$Document";
-        let column_document_id = env
-            .get_static_field(document_class, "COLUMN_DOCUMENT_ID", "Ljava/lang/String;")?
-            .l()?;
-        let column_display_name = env
-            .get_static_field(document_class, "COLUMN_DISPLAY_NAME", "Ljava/lang/String;")?
-            .l()?;
-        let column_size = env
-            .get_static_field(document_class, "COLUMN_SIZE", "Ljava/lang/String;")?
-            .l()?;
-        let column_mime_type = env
-            .get_static_field(document_class, "COLUMN_MIME_TYPE", "Ljava/lang/String;")?
-            .l()?;
+    /// The display path and URI string DocumentsContract.buildDocumentUriUsingTree gives the
+    /// document "document_id" of the tree.
+    fn child(&self, document_id: &str) -> (String, String) {
+        (
+            format!("{}/document/{}", self.path, document_id),
+            format!("{}/document/{}", self.url, uri_encode(document_id)),
+        )
+    }
+}
 
-        let projection = env.new_object_array(4, "java/lang/String", JObject::null())?;
-        env.set_object_array_element(&projection, 0, column_document_id)?;
-        env.set_object_array_element(&projection, 1, column_display_name)?;
-        env.set_object_array_element(&projection, 2, column_size)?;
-        env.set_object_array_element(&projection, 3, column_mime_type)?;
+/// Look up a direct child of a directory DocumentFile by display name with findFile.
+/// Returns None when there is no such child.
+fn find_child_document<'local>(
+    env: &mut JNIEnv<'local>,
+    directory: &JObject,
+    name: &str,
+) -> Result<Option<JObject<'local>>> {
+    let name_str = env.new_string(name)?;
+    let child = env
+        .call_method(
+            directory,
+            "findFile",
+            "(Ljava/lang/String;)Landroidx/documentfile/provider/DocumentFile;",
+            &[JValueGen::Object(&name_str)],
+        )?
+        .l()?;
+    Ok((!child.is_null()).then_some(child))
+}
 
-        // Query
-        let cursor = env
-            .call_method(
-                &content_resolver,
-                "query",
-                "(Landroid/net/Uri;[Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;)Landroid/database/Cursor;",
-                &[
-                    JValueGen::Object(&children_uri),
-                    JValueGen::Object(&projection),
-                    JValueGen::Object(&JObject::null()),
-                    JValueGen::Object(&JObject::null()),
-                    JValueGen::Object(&JObject::null()),
-                ],
-            )?
-            .l()?;
+// MIME type of directories, DocumentsContract.Document.MIME_TYPE_DIR
+const MIME_TYPE_DIR: &str = "vnd.android.document/directory";
 
-        // Get MIME type for directory to compare against
-        let mime_type_dir = env
-            .get_static_field(document_class, "MIME_TYPE_DIR", "Ljava/lang/String;")?
-            .l()?;
+// MIME type of files of unknown type
+const MIME_TYPE_BINARY: &str = "application/octet-stream";
 
-        let mut files = Vec::new();
-        // Check if cursor is not null
-        if !cursor.is_null() {
-            // Iterate through the cursor
-            while env.call_method(&cursor, "moveToNext", "()Z", &[])?.z()? {
-                // Get column values
-                let doc_id_jstr: JString = env
-                    .call_method(
-                        &cursor,
-                        "getString",
-                        "(I)Ljava/lang/String;",
-                        &[JValueGen::Int(0)],
-                    )?
-                    .l()?
-                    .into();
-                let _doc_id = env.get_string(&doc_id_jstr)?;
+// Columns projected when querying the children of a directory, in cursor index order
+const CHILD_COLUMNS: [&str; 6] = [
+    "COLUMN_DOCUMENT_ID",
+    "COLUMN_DISPLAY_NAME",
+    "COLUMN_SIZE",
+    "COLUMN_MIME_TYPE",
+    "COLUMN_LAST_MODIFIED",
+    "COLUMN_FLAGS",
+];
 
-                let filename_jstr: JString = env
-                    .call_method(
-                        &cursor,
-                        "getString",
-                        "(I)Ljava/lang/String;",
-                        &[JValueGen::Int(1)],
-                    )?
-                    .l()?
-                    .into();
-                let filename = env
-                    .get_string(&filename_jstr)?
-                    .to_string_lossy()
-                    .into_owned();
-
-                let size = env
-                    .call_method(&cursor, "getLong", "(I)J", &[JValueGen::Int(2)])?
-                    .j()? as usize;
-
-                let mime_type_jstr: JString = env
-                    .call_method(
-                        &cursor,
-                        "getString",
-                        "(I)Ljava/lang/String;",
-                        &[JValueGen::Int(3)],
-                    )?
-                    .l()?
-                    .into();
+// A row of the children query, see CHILD_COLUMNS
+struct ChildRow<'local> {
+    document_id_jstr: JObject<'local>,
+    document_id: String,
+    filename: String,
+    size: u64,
+    mime_type: String,
+    last_modified: i64,
+    flags: i32,
+}
 
-                // Build child URI
-                let child_uri = env
-                    .call_static_method(
-                        documents_contract_class,
+impl ChildRow<'_> {
+    fn is_dir(&self) -> bool {
+        self.mime_type == MIME_TYPE_DIR
+    }
+}
+
+/// Build a projection array from the names of DocumentsContract.Document column constants.
+fn document_projection<'local>(
+    env: &mut JNIEnv<'local>,
+    columns: &[&str],
+) -> Result<JObjectArray<'local>> {
+    let document_class = "android/provider/DocumentsContract$Document";
+    let projection =
+        env.new_object_array(columns.len() as i32, "java/lang/String", JObject::null())?;
+    for (index, column) in columns.iter().enumerate() {
+        let column_name = env
+            .get_static_field(document_class, column, "Ljava/lang/String;")?
+            .l()?;
+        env.set_object_array_element(&projection, index as i32, column_name)?;
+    }
+    Ok(projection)
+}
+
+/// Read a string column of the current cursor row, mapping SQL NULL to None.
+fn cursor_string(env: &mut JNIEnv, cursor: &JObject, index: i32) -> Result<Option<String>> {
+    let value = env
+        .call_method(
+            cursor,
+            "getString",
+            "(I)Ljava/lang/String;",
+            &[JValueGen::Int(index)],
+        )?
+        .l()?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(jstring_to_string(env, value)?))
+}
+
+/// Query a single long column of a document, given the name of a DocumentsContract.Document
+/// column constant (e.g. "COLUMN_FLAGS"). Returns None if the provider returns no row or a NULL
+/// value for the column.
+pub(crate) fn query_document_long(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    uri: &JObject,
+    column: &str,
+) -> Result<Option<i64>> {
+    let content_resolver = content_resolver(env, context)?;
+    let projection = document_projection(env, &[column])?;
+    let cursor = env
+        .call_method(
+            &content_resolver,
+            "query",
+            "(Landroid/net/Uri;[Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;)Landroid/database/Cursor;",
+            &[
+                JValueGen::Object(uri),
+                JValueGen::Object(&projection),
+                JValueGen::Object(&JObject::null()),
+                JValueGen::Object(&JObject::null()),
+                JValueGen::Object(&JObject::null()),
+            ],
+        )?
+        .l()?;
+    if cursor.is_null() {
+        return Ok(None);
+    }
+
+    let result = (|| -> Result<Option<i64>> {
+        if !env.call_method(&cursor, "moveToFirst", "()Z", &[])?.z()? {
+            return Ok(None);
+        }
+        if env
+            .call_method(&cursor, "isNull", "(I)Z", &[JValueGen::Int(0)])?
+            .z()?
+        {
+            return Ok(None);
+        }
+        Ok(Some(
+            env.call_method(&cursor, "getLong", "(I)J", &[JValueGen::Int(0)])?
+                .j()?,
+        ))
+    })();
+
+    // Close the cursor
+    env.call_method(&cursor, "close", "()V", &[])?.v()?;
+    result
+}
+
+/// Query a single column of a document by its raw column name and read it as a string. Returns
+/// None if the provider returns no row, does not know the column or has NULL in it.
+fn query_document_string(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    uri: &JObject,
+    column: &str,
+) -> Result<Option<String>> {
+    let content_resolver = content_resolver(env, context)?;
+    let projection = env.new_object_array(1, "java/lang/String", JObject::null())?;
+    let column_name = env.new_string(column)?;
+    env.set_object_array_element(&projection, 0, column_name)?;
+    let cursor = env.call_method(
+        &content_resolver,
+        "query",
+        "(Landroid/net/Uri;[Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;)Landroid/database/Cursor;",
+        &[
+            JValueGen::Object(uri),
+            JValueGen::Object(&projection),
+            JValueGen::Object(&JObject::null()),
+            JValueGen::Object(&JObject::null()),
+            JValueGen::Object(&JObject::null()),
+        ],
+    );
+    if cursor.is_err() {
+        // SQLite-backed providers throw for columns they do not have
+        env.exception_clear()?;
+        return Ok(None);
+    }
+    let cursor = cursor?.l()?;
+    if cursor.is_null() {
+        return Ok(None);
+    }
+
+    let result = (|| -> Result<Option<String>> {
+        if !env.call_method(&cursor, "moveToFirst", "()Z", &[])?.z()? {
+            return Ok(None);
+        }
+        cursor_string_by_name(env, &cursor, column)
+    })();
+
+    // Close the cursor
+    env.call_method(&cursor, "close", "()V", &[])?.v()?;
+    result
+}
+
+/// Query the children of a tree directory URI and call "f" for every row. Each row is processed
+/// in its own JNI local frame, so local references do not pile up in large directories, and the
+/// cursor is closed whether or not "f" succeeds. A failed query (null cursor) is a QueryFailed
+/// error, or PermissionDenied when the permission on the tree is gone; an empty directory is not.
+fn for_each_child_row<F>(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    parent_uri: &JObject,
+    f: F,
+) -> Result<()>
+where
+    F: for<'a> FnMut(&mut JNIEnv<'a>, ChildRow<'a>) -> Result<()>,
+{
+    for_each_child_row_where(env, context, parent_uri, None, None, None, f)
+}
+
+/// Like `for_each_child_row`, passing "selection" (an SQL WHERE clause and its arguments) and
+/// "sort_order" (an SQL ORDER BY clause) to the children query. Most providers ignore both, so
+/// callers must filter and sort the rows as well; a query failing with a sort order is retried
+/// without one. With "cancellation", the query is aborted
+/// through its CancellationSignal and the token is checked before every row.
+fn for_each_child_row_where<F>(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    parent_uri: &JObject,
+    selection: Option<(&str, &[&str])>,
+    sort_order: Option<&str>,
+    cancellation: Option<&CancellationToken>,
+    mut f: F,
+) -> Result<()>
+where
+    F: for<'a> FnMut(&mut JNIEnv<'a>, ChildRow<'a>) -> Result<()>,
+{
+    let cursor = query_children(
+        env,
+        context,
+        parent_uri,
+        selection,
+        sort_order,
+        cancellation,
+    )?;
+
+    // Iterate through the cursor
+    let result = (|| -> Result<()> {
+        while env.call_method(&cursor, "moveToNext", "()Z", &[])?.z()? {
+            if let Some(cancellation) = cancellation {
+                cancellation.check("Listing")?;
+            }
+            env.with_local_frame(16, |env| -> Result<()> {
+                match read_child_row(env, &cursor)? {
+                    Some(row) => f(env, row),
+                    None => Ok(()),
+                }
+            })?;
+        }
+        Ok(())
+    })();
+
+    // Close the cursor
+    env.call_method(&cursor, "close", "()V", &[])?.v()?;
+    result
+}
+
+/// Query the children of a tree directory URI, see `for_each_child_row_where`. <br />
+/// RETURNS: The cursor, never null; the caller must close it. <br />
+fn query_children<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &GlobalRef,
+    parent_uri: &JObject,
+    selection: Option<(&str, &[&str])>,
+    sort_order: Option<&str>,
+    cancellation: Option<&CancellationToken>,
+) -> Result<JObject<'local>> {
+    // Get ContentResolver
+    let content_resolver = content_resolver(env, context)?;
+
+    let documents_contract_class = "android/provider/DocumentsContract";
+    // Get document ID of parent URI
+    let parent_document_id = env
+        .call_static_method(
+            documents_contract_class,
+            "getDocumentId",
+            "(Landroid/net/Uri;)Ljava/lang/String;",
+            &[JValueGen::Object(parent_uri)],
+        )?
+        .l()?;
+
+    // Build children URI
+    let children_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "buildChildDocumentsUriUsingTree",
+            "(Landroid/net/Uri;Ljava/lang/String;)Landroid/net/Uri;",
+            &[
+                JValueGen::Object(parent_uri),
+                JValueGen::Object(&parent_document_id),
+            ],
+        )?
+        .l()?;
+
+    // Define projection
+    let projection = document_projection(env, &CHILD_COLUMNS)?;
+
+    // Build the selection, if any
+    let (selection_str, selection_args) = match selection {
+        Some((clause, args)) => {
+            let selection_args =
+                env.new_object_array(args.len() as i32, "java/lang/String", JObject::null())?;
+            for (index, arg) in args.iter().enumerate() {
+                let arg = env.new_string(arg)?;
+                env.set_object_array_element(&selection_args, index as i32, arg)?;
+            }
+            (
+                JObject::from(env.new_string(clause)?),
+                JObject::from(selection_args),
+            )
+        }
+        None => (JObject::null(), JObject::null()),
+    };
+    let sort_order_str = match sort_order {
+        Some(sort_order) => JObject::from(env.new_string(sort_order)?),
+        None => JObject::null(),
+    };
+
+    let null_signal = JObject::null();
+    let signal = cancellation.map_or(&null_signal, |cancellation| cancellation.signal().as_obj());
+
+    // Query, providers throw e.g. a SecurityException when the grant is gone, and an
+    // OperationCanceledException when the signal is cancelled
+    let query = |env: &mut JNIEnv<'local>, sort_order: &JObject| {
+        env.call_method(
+            &content_resolver,
+            "query",
+            "(Landroid/net/Uri;[Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;Landroid/os/CancellationSignal;)Landroid/database/Cursor;",
+            &[
+                JValueGen::Object(&children_uri),
+                JValueGen::Object(&projection),
+                JValueGen::Object(&selection_str),
+                JValueGen::Object(&selection_args),
+                JValueGen::Object(sort_order),
+                JValueGen::Object(signal),
+            ],
+        )
+    };
+    let mut cursor = query(env, &sort_order_str);
+    let mut thrown = check_exception(env);
+    // Some providers reject orders they cannot apply; callers sort in memory anyway
+    if !sort_order_str.is_null() {
+        if let Err(e) = &thrown {
+            if !matches!(e, SafError::PermissionDenied(_) | SafError::Cancelled(_)) {
+                info!("The sorted children query failed, retrying unsorted: {}", e);
+                cursor = query(env, &JObject::null());
+                thrown = check_exception(env);
+            }
+        }
+    }
+    thrown?;
+    let cursor = cursor?.l()?;
+
+    // A null cursor means the query failed, which must not look like an empty directory
+    if cursor.is_null() {
+        let url = object_to_string(env, parent_uri)?;
+        let tree_uri = owning_tree_uri(env, parent_uri)?;
+        let permission = permission_level_of(env, context, &tree_uri)?;
+        return Err(null_cursor_error(&url, permission));
+    }
+    Ok(cursor)
+}
+
+/// The error for a children query that returned a null cursor: PermissionDenied when the grant
+/// on the tree is gone, QueryFailed otherwise.
+fn null_cursor_error(url: &str, permission: PermissionLevel) -> SafError {
+    if permission == PermissionLevel::None {
+        return SafError::PermissionDenied(format!("Permission denied: cannot list {}", url));
+    }
+    SafError::QueryFailed(format!(
+        "The provider returned no cursor for the children of {}",
+        url
+    ))
+}
+
+/// Read the current row of a children cursor. <br />
+/// RETURNS: None for rows without a document ID, which are skipped. <br />
+fn read_child_row<'local>(
+    env: &mut JNIEnv<'local>,
+    cursor: &JObject,
+) -> Result<Option<ChildRow<'local>>> {
+    let document_id_jstr = env
+        .call_method(
+            cursor,
+            "getString",
+            "(I)Ljava/lang/String;",
+            &[JValueGen::Int(0)],
+        )?
+        .l()?;
+    if document_id_jstr.is_null() {
+        return Ok(None);
+    }
+    let document_id = env
+        .get_string(<&JString>::from(&document_id_jstr))?
+        .to_string_lossy()
+        .into_owned();
+    let filename = cursor_string(env, cursor, 1)?.unwrap_or_default();
+    let size = env
+        .call_method(cursor, "getLong", "(I)J", &[JValueGen::Int(2)])?
+        .j()?;
+    let size = size_from_provider(size);
+    let mime_type = cursor_string(env, cursor, 3)?.unwrap_or_default();
+    let last_modified = env
+        .call_method(cursor, "getLong", "(I)J", &[JValueGen::Int(4)])?
+        .j()?;
+    let flags = env
+        .call_method(cursor, "getInt", "(I)I", &[JValueGen::Int(5)])?
+        .i()?;
+
+    Ok(Some(ChildRow {
+        document_id_jstr,
+        document_id,
+        filename,
+        size,
+        mime_type,
+        last_modified,
+        flags,
+    }))
+}
+
+/// Build an AndroidFile object for a row of the children query of "parent_uri", the URI of the
+/// DocumentFile "parent". The path and URL are derived from "tree_urls" rather than read from
+/// the child URI, saving two JNI round trips per entry.
+fn child_from_row(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    parent: &JObject,
+    parent_uri: &JObject,
+    tree_urls: &TreeUrls,
+    row: &ChildRow,
+) -> Result<AndroidFile> {
+    // Build child URI
+    let child_uri = env
+        .call_static_method(
+            "android/provider/DocumentsContract",
+            "buildDocumentUriUsingTree",
+            "(Landroid/net/Uri;Ljava/lang/String;)Landroid/net/Uri;",
+            &[
+                JValueGen::Object(parent_uri),
+                JValueGen::Object(&row.document_id_jstr),
+            ],
+        )?
+        .l()?;
+
+    let (path, url) = tree_urls.child(&row.document_id);
+
+    // Create a TreeDocumentFile linked to the parent, not a SingleDocumentFile, so listed
+    // directories can create and list children like those from from_tree_url
+    let document_file = new_tree_document_file(env, parent, context.as_obj(), &child_uri)?;
+
+    Ok(AndroidFile {
+        filename: row.filename.clone(),
+        size: row.size,
+        path,
+        url,
+        is_dir: row.is_dir(),
+        last_modified: (row.last_modified > 0).then_some(row.last_modified),
+        mime_type: row.mime_type.clone(),
+        document_id: row.document_id.clone(),
+        flags: Some(DocumentFlags(row.flags)),
+        document_file: env.new_global_ref(&document_file)?,
+    })
+}
+
+/// List the children of a directory on the filesystem (see app_external_directory), unsorted.
+/// These cannot be queried through DocumentsContract, so they are listed through the
+/// DocumentFile instead.
+fn list_filesystem_children(env: &mut JNIEnv, directory: &AndroidFile) -> Result<Vec<AndroidFile>> {
+    let children = JObjectArray::from(
+        env.call_method(
+            &directory.document_file,
+            "listFiles",
+            "()[Landroidx/documentfile/provider/DocumentFile;",
+            &[],
+        )?
+        .l()?,
+    );
+    let count = env.get_array_length(&children)?;
+    let mut files = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        let child = env.get_object_array_element(&children, index)?;
+        files.push(from_document_file(&child)?);
+        env.delete_local_ref(child)?;
+    }
+    Ok(files)
+}
+
+/// List the children of "directory" in the given order, on an already attached JNIEnv. The order
+/// is requested from the provider and applied in memory as well, since most providers ignore it.
+fn list_children(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    directory: &AndroidFile,
+    order: SortOrder,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Vec<AndroidFile>> {
+    if is_file_url(&directory.url) {
+        let mut files = list_filesystem_children(env, directory)?;
+        files.sort_by(|a, b| order.compare(a, b));
+        return Ok(files);
+    }
+
+    // Parse parent URI from the directory URL
+    let parent_uri = parse_uri(env, &directory.url)?;
+    let mut files = Vec::new();
+    let sort_order = order.sql();
+    let tree_urls = TreeUrls::of(env, &parent_uri)?;
+    for_each_child_row_where(
+        env,
+        context,
+        &parent_uri,
+        None,
+        sort_order,
+        cancellation,
+        |env, row| {
+            files.push(child_from_row(
+                env,
+                context,
+                directory.document_file.as_obj(),
+                &parent_uri,
+                &tree_urls,
+                &row,
+            )?);
+            Ok(())
+        },
+    )?;
+
+    // Sort files, whether or not the provider did
+    files.sort_by(|a, b| order.compare(a, b));
+
+    Ok(files)
+}
+
+/// List the children of "directory" as entries of a walk at "depth", their relative paths
+/// starting with "prefix". Local references are released before returning.
+fn walk_children(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    directory: &AndroidFile,
+    depth: usize,
+    prefix: &str,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Vec<WalkEntry>> {
+    let children = env.with_local_frame(16, |env| {
+        list_children(env, context, directory, SortOrder::NameAsc, cancellation)
+    })?;
+    Ok(children
+        .into_iter()
+        .map(|file| WalkEntry {
+            relative_path: match prefix {
+                "" => file.filename.clone(),
+                _ => format!("{}/{}", prefix, file.filename),
+            },
+            file,
+            depth,
+        })
+        .collect())
+}
+
+/// Walk the tree below "root", see `AndroidFileOps::walk` and `walk_with_cancellation`.
+fn walk_tree(
+    root: &AndroidFile,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Vec<WalkEntry>> {
+    if !root.is_dir {
+        return Err(SafError::NotADirectory);
+    }
+    info!("Walking directory: {}", root.url);
+
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    // Directories are identified by document ID, or by URL where there is none (file://)
+    let visit_key = |file: &AndroidFile| {
+        if file.document_id.is_empty() {
+            file.url.clone()
+        } else {
+            file.document_id.clone()
+        }
+    };
+    let mut visited = HashSet::from([visit_key(root)]);
+
+    // Children are pushed in reverse, so they are popped in name order
+    let mut entries = Vec::new();
+    let mut pending = walk_children(env, &context, root, 0, "", cancellation)?;
+    pending.reverse();
+    while let Some(entry) = pending.pop() {
+        if entry.file.is_dir && visited.insert(visit_key(&entry.file)) {
+            if let Some(cancellation) = cancellation {
+                cancellation.check("Walking")?;
+            }
+            let children = walk_children(
+                env,
+                &context,
+                &entry.file,
+                entry.depth + 1,
+                &entry.relative_path,
+                cancellation,
+            )?;
+            pending.extend(children.into_iter().rev());
+        }
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Feed "reader" to "update" in chunks of HASH_CHUNK_LEN bytes, until EOF.
+#[cfg(feature = "hashing")]
+fn hash_chunks<R: Read>(mut reader: R, mut update: impl FnMut(&[u8])) -> Result<()> {
+    let mut chunk = vec![0u8; HASH_CHUNK_LEN];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        update(&chunk[..read]);
+    }
+}
+
+/// SHA-256 digest of everything "reader" yields, see `AndroidFileOps::sha256`.
+#[cfg(feature = "hashing")]
+fn sha256_of<R: Read>(reader: R) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    hash_chunks(reader, |chunk| hasher.update(chunk))?;
+    Ok(hasher.finalize().into())
+}
+
+/// CRC-32 (IEEE) checksum of everything "reader" yields, see `AndroidFileOps::crc32`.
+#[cfg(feature = "hashing")]
+fn crc32_of<R: Read>(reader: R) -> Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    hash_chunks(reader, |chunk| hasher.update(chunk))?;
+    Ok(hasher.finalize())
+}
+
+/// Convert a size reported by a provider (a Java long) to bytes. Providers report -1 or other
+/// negative values for unknown sizes, which become 0 instead of wrapping around.
+pub(crate) fn size_from_provider(size: i64) -> u64 {
+    size.max(0) as u64
+}
+
+/// Get the SDK version of the running Android system (Build.VERSION.SDK_INT).
+pub(crate) fn sdk_int(env: &mut JNIEnv) -> Result<i32> {
+    Ok(env
+        .get_static_field("android/os/Build$VERSION", "SDK_INT", "I")?
+        .i()?)
+}
+
+/// Mark the MediaStore item backing a document as trashed or restore it, by updating
+/// MediaStore.MediaColumns.IS_TRASHED. Only available on Android 11 (API 30) and above.
+fn set_trashed(file: &AndroidFile, trashed: bool) -> Result<()> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+
+    if sdk_int(env)? < 30 {
+        return Err(SafError::Unsupported(
+            "Trash is only supported on Android 11 (API 30) and above".to_string(),
+        ));
+    }
+    let context = get_application_context()?;
+    let document_uri = parse_uri(env, &file.url)?;
+
+    // Map the document to its MediaStore item; this fails for documents that are not media
+    let media_uri = env.call_static_method(
+        "android/provider/MediaStore",
+        "getMediaUri",
+        "(Landroid/content/Context;Landroid/net/Uri;)Landroid/net/Uri;",
+        &[
+            JValueGen::Object(context.as_obj()),
+            JValueGen::Object(&document_uri),
+        ],
+    );
+    check_exception(env).map_err(|e| match e {
+        SafError::ProviderException(message)
+            if message.starts_with("java.lang.IllegalArgumentException") =>
+        {
+            SafError::Unsupported(format!("Document is not backed by MediaStore: {}", message))
+        }
+        e => e,
+    })?;
+    let media_uri = media_uri?.l()?;
+    if media_uri.is_null() {
+        return Err(SafError::Unsupported(
+            "Document is not backed by MediaStore".to_string(),
+        ));
+    }
+
+    // ContentValues { IS_TRASHED: 0 | 1 }
+    let values = env.new_object("android/content/ContentValues", "()V", &[])?;
+    let column = env.new_string("is_trashed")?;
+    let value = env
+        .call_static_method(
+            "java/lang/Integer",
+            "valueOf",
+            "(I)Ljava/lang/Integer;",
+            &[JValueGen::Int(trashed as i32)],
+        )?
+        .l()?;
+    env.call_method(
+        &values,
+        "put",
+        "(Ljava/lang/String;Ljava/lang/Integer;)V",
+        &[JValueGen::Object(&column), JValueGen::Object(&value)],
+    )?;
+
+    let content_resolver = content_resolver(env, &context)?;
+    let updated = env.call_method(
+        &content_resolver,
+        "update",
+        "(Landroid/net/Uri;Landroid/content/ContentValues;Ljava/lang/String;[Ljava/lang/String;)I",
+        &[
+            JValueGen::Object(&media_uri),
+            JValueGen::Object(&values),
+            JValueGen::Object(&JObject::null()),
+            JValueGen::Object(&JObject::null()),
+        ],
+    );
+    check_exception(env)?;
+    let updated = updated?.i()?;
+    if updated == 0 {
+        return Err(SafError::NotFound("MediaStore item not found".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Create an AndroidFile object from a content tree URL obtained from Storage Access Framework (SAF).
+pub fn from_tree_url(url: &str) -> Result<AndroidFile> {
+    info!("Creating AndroidFile object from URL: {}", url);
+    ensure_initialized()?;
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    // Convert Rust string to Java string, and parse it as a URI
+    let url_str = env.new_string(url)?;
+    let uri = env
+        .call_static_method(
+            "android/net/Uri",
+            "parse",
+            "(Ljava/lang/String;)Landroid/net/Uri;",
+            &[JValueGen::Object(&url_str)],
+        )?
+        .l()?;
+
+    // Get the parent DocumentFile
+    let document_file_class = find_class("androidx/documentfile/provider/DocumentFile")?;
+    let parent = env.call_static_method(
+        document_file_class,
+        "fromTreeUri",
+        "(Landroid/content/Context;Landroid/net/Uri;)Landroidx/documentfile/provider/DocumentFile;",
+        &[JValueGen::Object(context.as_obj()), JValueGen::Object(&uri)],
+    )?.l()?;
+
+    // Check if parent URI starts with the input URI, in which case we can use the parent directly.
+    let parent_uri = env
+        .call_method(&parent, "getUri", "()Landroid/net/Uri;", &[])?
+        .l()?;
+
+    let parent_uri_str = env
+        .call_method(&parent_uri, "toString", "()Ljava/lang/String;", &[])?
+        .l()?;
+
+    let input_uri_str = env
+        .call_method(&uri, "toString", "()Ljava/lang/String;", &[])?
+        .l()?;
+
+    let parent_str: String = env.get_string(&parent_uri_str.into())?.into();
+    let input_str: String = env.get_string(&input_uri_str.into())?.into();
+
+    if parent_str.starts_with(&input_str) {
+        return from_document_file(&parent);
+    }
+
+    // Otherwise, we create a TreeDocumentFile pointing to child file.
+    let document_file = new_tree_document_file(env, &parent, context.as_obj(), &uri)?;
+
+    from_document_file(&document_file)
+}
+
+/// Create an AndroidFile object from a single document URL, e.g. one returned by
+/// ACTION_OPEN_DOCUMENT or received through a share intent, with DocumentFile.fromSingleUri.
+/// Such a document has no tree to list or create children in, so `is_dir` is always false;
+/// a directory document keeps its directory MIME type.
+pub fn from_single_uri(url: &str) -> Result<AndroidFile> {
+    info!("Creating AndroidFile object from single URL: {}", url);
+    ensure_initialized()?;
+    let document_file = {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        let uri = parse_uri(env, url)?;
+        let document_file_class = find_class("androidx/documentfile/provider/DocumentFile")?;
+        let document_file = env.call_static_method(
+            document_file_class,
+            "fromSingleUri",
+            "(Landroid/content/Context;Landroid/net/Uri;)Landroidx/documentfile/provider/DocumentFile;",
+            &[JValueGen::Object(context.as_obj()), JValueGen::Object(&uri)],
+        );
+        check_exception(env)?;
+        env.new_global_ref(document_file?.l()?)?
+    };
+
+    let mut file = from_document_file(document_file.as_obj())?;
+    file.is_dir = false;
+    Ok(file)
+}
+
+/// Get an AndroidFile object for a tree from its provider authority and tree document ID, e.g.
+/// "com.android.externalstorage.documents" and "primary:Music", the two identifiers a tree URI
+/// is built from (DocumentsContract.buildTreeDocumentUri). Storing these instead of the full
+/// URI string is more compact; `authority` and `tree_document_id` give them back. The app needs
+/// a (persisted) grant on the tree, as with `from_tree_url`.
+pub fn from_tree_parts(authority: &str, tree_document_id: &str) -> Result<AndroidFile> {
+    ensure_initialized()?;
+    let url = {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        let authority_str = env.new_string(authority)?;
+        let tree_document_id_str = env.new_string(tree_document_id)?;
+        let tree_uri = env
+            .call_static_method(
+                "android/provider/DocumentsContract",
+                "buildTreeDocumentUri",
+                "(Ljava/lang/String;Ljava/lang/String;)Landroid/net/Uri;",
+                &[
+                    JValueGen::Object(&authority_str),
+                    JValueGen::Object(&tree_document_id_str),
+                ],
+            )?
+            .l()?;
+        object_to_string(env, &tree_uri)?
+    };
+
+    from_tree_url(&url)
+}
+
+/// Construct a TreeDocumentFile for a tree document URI. Unlike SingleDocumentFile, it can list
+/// and create children. "parent" is the DocumentFile returned by getParentFile and may be null.
+fn new_tree_document_file<'local>(
+    env: &mut JNIEnv<'local>,
+    parent: &JObject,
+    context: &JObject,
+    uri: &JObject,
+) -> Result<JObject<'local>> {
+    let tree_document_file_class = find_class("androidx/documentfile/provider/TreeDocumentFile")?;
+    Ok(env.new_object(
+        tree_document_file_class,
+        "(Landroidx/documentfile/provider/DocumentFile;Landroid/content/Context;Landroid/net/Uri;)V",
+        &[
+            JValueGen::Object(parent),
+            JValueGen::Object(context),
+            JValueGen::Object(uri),
+        ],
+    )?)
+}
+
+/// Rename a document with DocumentsContract.renameDocument and return an AndroidFile object for
+/// the renamed document. Providers may change the URI (and document ID) on rename, so the new
+/// object is built from the URI they return.
+fn rename_document(file: &AndroidFile, display_name: &str) -> Result<AndroidFile> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    let uri = parse_uri(env, &file.url)?;
+    let content_resolver = content_resolver(env, &context)?;
+    let display_name_str = env.new_string(display_name)?;
+    let documents_contract_class = "android/provider/DocumentsContract";
+    let renamed_uri = env.call_static_method(
+        documents_contract_class,
+        "renameDocument",
+        "(Landroid/content/ContentResolver;Landroid/net/Uri;Ljava/lang/String;)Landroid/net/Uri;",
+        &[
+            JValueGen::Object(&content_resolver),
+            JValueGen::Object(&uri),
+            JValueGen::Object(&display_name_str),
+        ],
+    );
+    // Providers without rename support throw UnsupportedOperationException, an Unsupported error
+    check_exception(env)?;
+    let mut renamed_uri = renamed_uri?.l()?;
+    if renamed_uri.is_null() {
+        // Providers keeping the document ID return no URI; before Android 10 a failed rename
+        // returns null as well, so tell the two apart by the current display name
+        let current_name = query_document_string(env, &context, &uri, "_display_name")?;
+        if current_name.as_deref() != Some(display_name) {
+            return Err(SafError::ProviderException(format!(
+                "Failed to rename {} to {}",
+                file.url, display_name
+            )));
+        }
+        renamed_uri = uri;
+    }
+
+    let is_tree_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "isTreeUri",
+            "(Landroid/net/Uri;)Z",
+            &[JValueGen::Object(&renamed_uri)],
+        )?
+        .z()?;
+    let renamed = if is_tree_uri {
+        // Keep the parent link, so the renamed object can still resolve its parent
+        let parent = env
+            .call_method(
+                &file.document_file,
+                "getParentFile",
+                "()Landroidx/documentfile/provider/DocumentFile;",
+                &[],
+            )?
+            .l()?;
+        new_tree_document_file(env, &parent, context.as_obj(), &renamed_uri)?
+    } else {
+        let document_file_class = find_class("androidx/documentfile/provider/DocumentFile")?;
+        env.call_static_method(
+            document_file_class,
+            "fromSingleUri",
+            "(Landroid/content/Context;Landroid/net/Uri;)Landroidx/documentfile/provider/DocumentFile;",
+            &[JValueGen::Object(context.as_obj()), JValueGen::Object(&renamed_uri)],
+        )?
+        .l()?
+    };
+
+    from_document_file(&renamed)
+}
+
+/// Create "file_name" in "directory" and fill it with the remaining content of "source", with
+/// `copy_file_contents`, or `copy_file_contents_cancellable` when a token is given. If the copy
+/// fails or is cancelled, the new file is removed again, so no truncated file is left behind.
+fn create_filled(
+    directory: &AndroidFile,
+    mime_type: &str,
+    file_name: &str,
+    source: &mut File,
+    cancellation: Option<&CancellationToken>,
+) -> Result<AndroidFile> {
+    let created = directory.create_file(mime_type, file_name)?;
+    let copied = created
+        .open("wt")
+        .and_then(|mut destination| match cancellation {
+            Some(cancellation) => {
+                copy_file_contents_cancellable(source, &mut destination, cancellation)
+            }
+            None => Ok(copy_file_contents(source, &mut destination)?),
+        });
+    match copied {
+        Ok(copied) => {
+            info!("Copied {} bytes into {}", copied, created.url);
+            Ok(created)
+        }
+        Err(e) => {
+            if let Err(delete_error) = created.delete() {
+                info!("Failed to remove {}: {}", created.url, delete_error);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Copy a file into "dest_dir", see `AndroidFileOps::copy_to` and `copy_to_with_cancellation`.
+fn copy_document(
+    file: &AndroidFile,
+    dest_dir: &AndroidFile,
+    new_name: Option<&str>,
+    cancellation: Option<&CancellationToken>,
+) -> Result<AndroidFile> {
+    if file.is_dir {
+        return Err(SafError::Unsupported(format!(
+            "Copying directories is not supported: {}",
+            file.url
+        )));
+    }
+    if !dest_dir.is_dir {
+        return Err(SafError::NotADirectory);
+    }
+    if let Some(cancellation) = cancellation {
+        cancellation.check("Copying")?;
+    }
+
+    let mime_type = if file.mime_type.is_empty() {
+        MIME_TYPE_BINARY
+    } else {
+        &file.mime_type
+    };
+
+    let mut source = file.open("r")?;
+    create_filled(
+        dest_dir,
+        mime_type,
+        new_name.unwrap_or(&file.filename),
+        &mut source,
+        cancellation,
+    )
+}
+
+/// Move a document into "dest_dir" with DocumentsContract.moveDocument (API 24+). The native move
+/// is only attempted when both are in the same tree and the provider advertises
+/// FLAG_SUPPORTS_MOVE for the document. Returns None when it does not apply, so the caller can
+/// fall back to a copy, and an error when the parent of the document cannot be determined.
+fn move_document(file: &AndroidFile, dest_dir: &AndroidFile) -> Result<Option<AndroidFile>> {
+    let Some(parent) = parent_document(file)? else {
+        return Ok(None);
+    };
+
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    if sdk_int(env)? < 24 {
+        return Ok(None);
+    }
+    let uri = parse_uri(env, &file.url)?;
+    let dest_uri = parse_uri(env, &dest_dir.url)?;
+
+    // Both documents must be reachable through the same tree grant
+    let tree_uri = owning_tree_uri(env, &uri)?;
+    let dest_tree_uri = owning_tree_uri(env, &dest_uri)?;
+    if object_to_string(env, &tree_uri)? != object_to_string(env, &dest_tree_uri)? {
+        return Ok(None);
+    }
+    let flags = query_document_long(env, &context, &uri, "COLUMN_FLAGS")?.unwrap_or(0);
+    if !DocumentFlags(flags as i32).supports_move() {
+        return Ok(None);
+    }
+
+    let parent_uri = parse_uri(env, &parent.url)?;
+    let content_resolver = content_resolver(env, &context)?;
+    let moved_uri = env.call_static_method(
+        "android/provider/DocumentsContract",
+        "moveDocument",
+        "(Landroid/content/ContentResolver;Landroid/net/Uri;Landroid/net/Uri;Landroid/net/Uri;)Landroid/net/Uri;",
+        &[
+            JValueGen::Object(&content_resolver),
+            JValueGen::Object(&uri),
+            JValueGen::Object(&parent_uri),
+            JValueGen::Object(&dest_uri),
+        ],
+    );
+    check_exception(env)?;
+    let moved_uri = moved_uri?.l()?;
+    if moved_uri.is_null() {
+        return Err(SafError::ProviderException(format!(
+            "Failed to move {} to {}",
+            file.url, dest_dir.url
+        )));
+    }
+
+    let moved = new_tree_document_file(
+        env,
+        dest_dir.document_file.as_obj(),
+        context.as_obj(),
+        &moved_uri,
+    )?;
+    Ok(Some(from_document_file(&moved)?))
+}
+
+/// Find the parent directory of a document, or None if the document is the root of its tree or
+/// a single document (not part of a tree), which has no parent.
+/// The parent is resolved with DocumentsContract.findDocumentPath where the provider supports
+/// it, since the DocumentFile parent chain is only accurate for objects reached by walking down
+/// from the tree root. An error is returned when the parent cannot be determined at all.
+fn parent_document(file: &AndroidFile) -> Result<Option<AndroidFile>> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+    let uri = env
+        .call_method(&file.document_file, "getUri", "()Landroid/net/Uri;", &[])?
+        .l()?;
+
+    let documents_contract_class = "android/provider/DocumentsContract";
+    let is_tree_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "isTreeUri",
+            "(Landroid/net/Uri;)Z",
+            &[JValueGen::Object(&uri)],
+        )?
+        .z()?;
+    if is_tree_uri {
+        // The tree root has no parent within the granted tree
+        let tree_document_id = env
+            .call_static_method(
+                documents_contract_class,
+                "getTreeDocumentId",
+                "(Landroid/net/Uri;)Ljava/lang/String;",
+                &[JValueGen::Object(&uri)],
+            )?
+            .l()?;
+        let document_id = env
+            .call_static_method(
+                documents_contract_class,
+                "getDocumentId",
+                "(Landroid/net/Uri;)Ljava/lang/String;",
+                &[JValueGen::Object(&uri)],
+            )?
+            .l()?;
+        let tree_document_id = jstring_to_string(env, tree_document_id)?;
+        if jstring_to_string(env, document_id)? == tree_document_id {
+            return Ok(None);
+        }
+
+        // The path holds the document IDs from the tree root down to the document itself
+        let content_resolver = content_resolver(env, &context)?;
+        let path = env.call_static_method(
+            documents_contract_class,
+            "findDocumentPath",
+            "(Landroid/content/ContentResolver;Landroid/net/Uri;)Landroid/provider/DocumentsContract$Path;",
+            &[JValueGen::Object(&content_resolver), JValueGen::Object(&uri)],
+        );
+        if path.is_err() {
+            // Providers without findDocumentPath support throw, fall back to getParentFile
+            env.exception_clear()?;
+        }
+        let path = path.ok().map(|path| path.l()).transpose()?;
+        if let Some(path) = path.filter(|path| !path.is_null()) {
+            let ids = env
+                .call_method(&path, "getPath", "()Ljava/util/List;", &[])?
+                .l()?;
+            let count = env.call_method(&ids, "size", "()I", &[])?.i()?;
+            if count >= 2 {
+                let parent_id = env
+                    .call_method(
+                        &ids,
+                        "get",
+                        "(I)Ljava/lang/Object;",
+                        &[JValueGen::Int(count - 2)],
+                    )?
+                    .l()?;
+                let parent_uri = env
+                    .call_static_method(
+                        documents_contract_class,
                         "buildDocumentUriUsingTree",
                         "(Landroid/net/Uri;Ljava/lang/String;)Landroid/net/Uri;",
-                        &[
-                            JValueGen::Object(&parent_uri),
-                            JValueGen::Object(&doc_id_jstr),
-                        ],
+                        &[JValueGen::Object(&uri), JValueGen::Object(&parent_id)],
+                    )?
+                    .l()?;
+                let parent =
+                    new_tree_document_file(env, &JObject::null(), context.as_obj(), &parent_uri)?;
+                return Ok(Some(from_document_file(&parent)?));
+            }
+        }
+    }
+
+    let parent = env
+        .call_method(
+            &file.document_file,
+            "getParentFile",
+            "()Landroidx/documentfile/provider/DocumentFile;",
+            &[],
+        )?
+        .l()?;
+    if parent.is_null() {
+        // SingleDocumentFile never has a parent
+        if !is_tree_uri {
+            return Ok(None);
+        }
+        return Err(SafError::Other(format!(
+            "The parent of {} cannot be determined",
+            file.url
+        )));
+    }
+    Ok(Some(from_document_file(&parent)?))
+}
+
+/// Find a direct child of a directory by display name, or None if there is no such child.
+pub(crate) fn child_document(file: &AndroidFile, name: &str) -> Result<Option<AndroidFile>> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+
+    find_child_document(env, file.document_file.as_obj(), name)?
+        .map(|child| from_document_file(&child))
+        .transpose()
+}
+
+/// Get an AndroidFile object for the app's own external files directory,
+/// Context.getExternalFilesDir(kind), e.g. "Pictures" for Environment.DIRECTORY_PICTURES, or the
+/// root of the directory if "kind" is empty. The directory is created if it does not exist.
+/// This storage is private to the app and needs no user grant; the object is backed by a file://
+/// URI and supports the same operations as objects from user-granted trees, listings included,
+/// except those that rely on provider features (e.g. document IDs, flags, quota). <br />
+/// RETURNS: An error if external storage is not available. <br />
+pub fn app_external_directory(kind: &str) -> Result<AndroidFile> {
+    info!(
+        "Creating AndroidFile object for external files directory: {}",
+        kind
+    );
+    ensure_initialized()?;
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    let kind_str = if kind.is_empty() {
+        JObject::null()
+    } else {
+        JObject::from(env.new_string(kind)?)
+    };
+    let directory = env
+        .call_method(
+            context.as_obj(),
+            "getExternalFilesDir",
+            "(Ljava/lang/String;)Ljava/io/File;",
+            &[JValueGen::Object(&kind_str)],
+        )?
+        .l()?;
+    if directory.is_null() {
+        return Err(SafError::NullDocument(
+            "External storage is not available".to_string(),
+        ));
+    }
+
+    let document_file_class = find_class("androidx/documentfile/provider/DocumentFile")?;
+    let document_file = env
+        .call_static_method(
+            document_file_class,
+            "fromFile",
+            "(Ljava/io/File;)Landroidx/documentfile/provider/DocumentFile;",
+            &[JValueGen::Object(&directory)],
+        )?
+        .l()?;
+
+    from_document_file(&document_file)
+}
+
+/// Delete several direct children of "dir" by name, e.g. when clearing a selection in a file
+/// manager. The directory is listed once and every entry is deleted like `delete`, all on one
+/// JVM attachment. A failure for one entry does not stop the others; it is logged and reported
+/// as false. <br />
+/// PARAMS: "names" - The display names of the entries to delete. <br />
+/// RETURNS: One pair per name, in input order, of the name and whether it was deleted. Names
+/// without a matching entry are reported as false. <br />
+pub fn delete_all(dir: &AndroidFile, names: &[&str]) -> Result<Vec<(String, bool)>> {
+    if !dir.is_dir {
+        return Err(SafError::NotADirectory);
+    }
+
+    // Keep the thread attached, so the deletes below do not attach and detach on every call
+    let _env_guard = get_env()?;
+    let children = dir.list_files()?;
+
+    Ok(names
+        .iter()
+        .map(|&name| {
+            let deleted = match children.iter().find(|child| child.filename == name) {
+                Some(child) => match child.delete() {
+                    Ok(()) => true,
+                    Err(e) => {
+                        info!("Failed to delete {}: {}", name, e);
+                        false
+                    }
+                },
+                None => false,
+            };
+            (name.to_string(), deleted)
+        })
+        .collect())
+}
+
+/// List the items of a MediaStore collection on the shared external storage, e.g. all images
+/// when the user granted media access (READ_MEDIA_IMAGES or READ_EXTERNAL_STORAGE) instead of
+/// a tree. The objects are backed by the content URI of each item, with the metadata read from
+/// the same query, and work with `open` and the other file operations; they have no document
+/// ID or flags, and only the items the app may access are returned. <br />
+/// PARAMS: "collection" - The collection to list. <br />
+/// RETURNS: The items sorted by name, or an Unsupported error for `MediaCollection::Downloads`
+/// before Android 10. <br />
+pub fn list_media(collection: MediaCollection) -> Result<Vec<AndroidFile>> {
+    info!("Listing the MediaStore collection {:?}", collection);
+    ensure_initialized()?;
+
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    env.with_local_frame(16, |env| -> Result<Vec<AndroidFile>> {
+        let collection_uri = env.get_static_field(
+            collection.class(),
+            "EXTERNAL_CONTENT_URI",
+            "Landroid/net/Uri;",
+        );
+        if collection_uri.is_err() {
+            env.exception_clear()?;
+            return Err(SafError::Unsupported(format!(
+                "The MediaStore collection {:?} is not available on this Android version",
+                collection
+            )));
+        }
+        let collection_uri = collection_uri?.l()?;
+
+        let projection =
+            env.new_object_array(MEDIA_COLUMNS.len() as i32, "java/lang/String", JObject::null())?;
+        for (index, column) in MEDIA_COLUMNS.iter().enumerate() {
+            let column_name = env.new_string(column)?;
+            env.set_object_array_element(&projection, index as i32, column_name)?;
+        }
+        let sort_order = env.new_string("_display_name ASC")?;
+        let content_resolver = content_resolver(env, &context)?;
+        let cursor = env.call_method(
+            &content_resolver,
+            "query",
+            "(Landroid/net/Uri;[Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;)Landroid/database/Cursor;",
+            &[
+                JValueGen::Object(&collection_uri),
+                JValueGen::Object(&projection),
+                JValueGen::Object(&JObject::null()),
+                JValueGen::Object(&JObject::null()),
+                JValueGen::Object(&sort_order),
+            ],
+        );
+        check_exception(env)?;
+        let cursor = cursor?.l()?;
+        if cursor.is_null() {
+            return Err(SafError::NullDocument(format!(
+                "MediaStore returned no cursor for {:?}",
+                collection
+            )));
+        }
+
+        let document_file_class = find_class("androidx/documentfile/provider/DocumentFile")?;
+        let mut files = Vec::new();
+        let result = (|| -> Result<()> {
+            while env.call_method(&cursor, "moveToNext", "()Z", &[])?.z()? {
+                let file = env.with_local_frame(16, |env| -> Result<AndroidFile> {
+                    let id = env
+                        .call_method(&cursor, "getLong", "(I)J", &[JValueGen::Int(0)])?
+                        .j()?;
+                    let filename = cursor_string(env, &cursor, 1)?.unwrap_or_default();
+                    let size = env
+                        .call_method(&cursor, "getLong", "(I)J", &[JValueGen::Int(2)])?
+                        .j()?;
+                    let mime_type = cursor_string(env, &cursor, 3)?.unwrap_or_default();
+                    // MediaStore reports seconds, AndroidFile milliseconds
+                    let date_modified = env
+                        .call_method(&cursor, "getLong", "(I)J", &[JValueGen::Int(4)])?
+                        .j()?;
+
+                    let uri = env
+                        .call_static_method(
+                            "android/content/ContentUris",
+                            "withAppendedId",
+                            "(Landroid/net/Uri;J)Landroid/net/Uri;",
+                            &[JValueGen::Object(&collection_uri), JValueGen::Long(id)],
+                        )?
+                        .l()?;
+                    let (path, url) = uri_path_and_url(env, &uri)?;
+                    let document_file = env
+                        .call_static_method(
+                            &document_file_class,
+                            "fromSingleUri",
+                            "(Landroid/content/Context;Landroid/net/Uri;)Landroidx/documentfile/provider/DocumentFile;",
+                            &[JValueGen::Object(context.as_obj()), JValueGen::Object(&uri)],
+                        )?
+                        .l()?;
+
+                    Ok(AndroidFile {
+                        filename,
+                        size: size_from_provider(size),
+                        path,
+                        url,
+                        is_dir: false,
+                        last_modified: (date_modified > 0).then_some(date_modified * 1000),
+                        mime_type,
+                        document_id: String::new(),
+                        flags: None,
+                        document_file: env.new_global_ref(document_file)?,
+                    })
+                })?;
+                files.push(file);
+            }
+            Ok(())
+        })();
+
+        // Close the cursor
+        env.call_method(&cursor, "close", "()V", &[])?.v()?;
+        result?;
+        Ok(files)
+    })
+}
+
+/// List several directories concurrently on a small pool of worker threads (at most 4), each
+/// attached to the JVM for its whole lifetime. Listing is IO-bound, so this speeds up e.g. a tree
+/// view expanding several folders at once. <br />
+/// RETURNS: One entry per input directory, in input order, pairing the directory URL with the
+/// result of `list_files` for it. A failure for one directory does not affect the others. <br />
+pub fn list_many(dirs: &[AndroidFile]) -> Vec<(String, Result<Vec<AndroidFile>>)> {
+    info!("Listing {} directories concurrently", dirs.len());
+
+    let next = AtomicUsize::new(0);
+    let workers = LIST_MANY_WORKERS.min(dirs.len());
+    let listings: Vec<(usize, Result<Vec<AndroidFile>>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    // Keep the thread attached, so the listings below do not attach and detach
+                    // on every call
+                    let _env_guard = get_env();
+                    let mut listings = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(dir) = dirs.get(index) else {
+                            break;
+                        };
+                        listings.push((index, dir.list_files()));
+                    }
+                    listings
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    });
+
+    let mut results: Vec<Option<Result<Vec<AndroidFile>>>> = dirs.iter().map(|_| None).collect();
+    for (index, listing) in listings {
+        results[index] = Some(listing);
+    }
+    dirs.iter()
+        .zip(results)
+        .map(|(dir, listing)| {
+            let listing = listing.unwrap_or_else(|| {
+                Err(SafError::Other(format!(
+                    "Listing {} did not complete",
+                    dir.url
+                )))
+            });
+            (dir.url.clone(), listing)
+        })
+        .collect()
+}
+
+/// Create an AndroidFile object from a DocumentFile Java object.
+pub fn from_document_file(document_file: &JObject) -> Result<AndroidFile> {
+    info!(
+        "Creating AndroidFile object from DocumentFile object: {:?}",
+        document_file
+    );
+    // First, check if document_file is null
+    if document_file.is_null() {
+        return Err(SafError::NullDocument(
+            "The provided DocumentFile object is null".to_string(),
+        ));
+    }
+
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+
+    // Obtain file size
+    let size = size_from_provider(env.call_method(document_file, "length", "()J", &[])?.j()?);
+
+    // Obtain file path and url
+    let uri = env
+        .call_method(document_file, "getUri", "()Landroid/net/Uri;", &[])?
+        .l()?;
+    let (path, url) = uri_path_and_url(env, &uri)?;
+
+    // Obtain file name; some providers report none, especially for single URIs, in which case
+    // the last segment of the URI (or of the document ID it holds) is used
+    let name = env
+        .call_method(document_file, "getName", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let filename = if name.is_null() {
+        let segment = env
+            .call_method(&uri, "getLastPathSegment", "()Ljava/lang/String;", &[])?
+            .l()?;
+        if segment.is_null() {
+            String::new()
+        } else {
+            let segment = jstring_to_string(env, segment)?;
+            match segment.rsplit_once('/') {
+                Some((_, last)) => last.to_string(),
+                None => segment,
+            }
+        }
+    } else {
+        jstring_to_string(env, name)?
+    };
+
+    // Check if the URL points to a directory
+    let is_dir = env
+        .call_method(document_file, "isDirectory", "()Z", &[])?
+        .z()
+        .unwrap_or(false);
+
+    // Obtain the modification time, 0 if the provider does not report one
+    let last_modified = env
+        .call_method(document_file, "lastModified", "()J", &[])?
+        .j()?;
+
+    // Obtain the MIME type; DocumentFile reports none for directories, empty if unknown
+    let mime_type = if is_dir {
+        MIME_TYPE_DIR.to_string()
+    } else {
+        let mime_type = env
+            .call_method(document_file, "getType", "()Ljava/lang/String;", &[])?
+            .l()?;
+        if mime_type.is_null() {
+            String::new()
+        } else {
+            jstring_to_string(env, mime_type)?
+        }
+    };
+
+    // Obtain the document ID, file:// and other non-document URIs have none
+    let context = get_application_context()?;
+    let documents_contract_class = "android/provider/DocumentsContract";
+    let is_document_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "isDocumentUri",
+            "(Landroid/content/Context;Landroid/net/Uri;)Z",
+            &[JValueGen::Object(context.as_obj()), JValueGen::Object(&uri)],
+        )?
+        .z()?
+        || env
+            .call_static_method(
+                documents_contract_class,
+                "isTreeUri",
+                "(Landroid/net/Uri;)Z",
+                &[JValueGen::Object(&uri)],
+            )?
+            .z()?;
+    let document_id = if is_document_uri {
+        document_id_of(env, &uri)?
+    } else {
+        String::new()
+    };
+
+    // Create GlobalRef from DocumentFile object
+    let document_file_ref = env.new_global_ref(document_file)?;
+
+    // Construct AndroidFile struct
+    Ok(AndroidFile {
+        filename,
+        size,
+        path,
+        url,
+        is_dir,
+        last_modified: (last_modified > 0).then_some(last_modified),
+        mime_type,
+        document_id,
+        flags: None,
+        document_file: document_file_ref,
+    })
+}
+
+/// Open a content URI with ContentResolver.openFileDescriptor and return the
+/// ParcelFileDescriptor as is.
+pub(crate) fn open_parcel_fd<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &GlobalRef,
+    url: &str,
+    open_mode: &str,
+) -> Result<JObject<'local>> {
+    // Get ContentResolver object from Context
+    let content_resolver = content_resolver(env, context)?;
+
+    // Convert URI string to Java Uri object, open mode to Java string
+    let uri = parse_uri(env, url)?;
+    let mode_str = env.new_string(open_mode)?;
+
+    // Providers report a missing document or permission by throwing
+    let parcel_fd = env.call_method(
+        content_resolver,
+        "openFileDescriptor",
+        "(Landroid/net/Uri;Ljava/lang/String;)Landroid/os/ParcelFileDescriptor;",
+        &[JValueGen::Object(&uri), JValueGen::Object(&mode_str)],
+    );
+    check_exception(env)?;
+    Ok(parcel_fd?.l()?)
+}
+
+/// Open a content URI with the given mode and return the detached file descriptor as a `File`.
+/// If the provider hands back a descriptor that collides with stdio (0-2), it is duplicated to a
+/// higher number and the original is closed, so native stdio usage never touches the document.
+pub fn open_content_url(url: &str, open_mode: &str) -> Result<File> {
+    info!("Opening file url: {}, with mode: {}", url, open_mode);
+    ensure_initialized()?;
+
+    // Obtain JNIEnv and Context using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    // Open the file descriptor and detach it
+    let parcel_fd = open_parcel_fd(env, &context, url, open_mode)?;
+    file_from_parcel_fd(env, &parcel_fd)
+}
+
+/// Detach the descriptor of a ParcelFileDescriptor and return it as a `File`, see
+/// `open_content_url`.
+fn file_from_parcel_fd(env: &mut JNIEnv, parcel_fd: &JObject) -> Result<File> {
+    let fd = env.call_method(parcel_fd, "detachFd", "()I", &[])?.i()? as RawFd;
+
+    // Validate file descriptor before creating File object
+    if fd < 0 {
+        return Err(SafError::Other(format!("Invalid file descriptor: {}", fd)));
+    }
+
+    // Take ownership of the detached descriptor, so it is closed on every path below
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // Some Android versions hand back a descriptor in the stdio range (0-2) when those were
+    // closed earlier. Native code writing to stdout/stderr would then silently write into the
+    // document, so move such descriptors above the stdio range and close the original.
+    // OwnedFd::try_clone uses F_DUPFD_CLOEXEC, which never returns a descriptor below 3.
+    let fd = if fd.as_raw_fd() <= STDERR_FILENO {
+        info!(
+            "Detached fd {} collides with stdio, duplicating it",
+            fd.as_raw_fd()
+        );
+        fd.try_clone()?
+    } else {
+        fd
+    };
+
+    // Create a new file from the validated file descriptor
+    Ok(File::from(fd))
+}
+
+impl AndroidFile {
+    /// The key listings are sorted by: the file name, then the document ID to order entries with
+    /// the same name deterministically. Names are compared by their UTF-8 bytes.
+    pub fn sort_key(&self) -> (&str, &str) {
+        (&self.filename, &self.document_id)
+    }
+
+    /// The raw document ID within the provider (DocumentsContract.getDocumentId), e.g. to build
+    /// child or tree URIs with DocumentsContract. Empty for file:// and other non-document URIs.
+    pub fn document_id(&self) -> &str {
+        &self.document_id
+    }
+
+    /// The content URI of the object as a `DocumentUri`, whose authority and document ID can be
+    /// read without JNI calls. <br />
+    /// RETURNS: An error for objects not backed by a content:// URI, e.g. those from
+    /// `app_external_directory`. <br />
+    pub fn uri(&self) -> Result<DocumentUri> {
+        DocumentUri::new(&self.url)
+    }
+
+    /// Size of the file in bytes, or None for directories, whose `size` field is undefined.
+    pub fn file_size(&self) -> Option<u64> {
+        (!self.is_dir).then_some(self.size)
+    }
+
+    /// The modification time as a SystemTime, or None if the provider does not report one.
+    pub fn modified_time(&self) -> Option<SystemTime> {
+        let millis = u64::try_from(self.last_modified?).ok()?;
+        UNIX_EPOCH.checked_add(Duration::from_millis(millis))
+    }
+
+    /// Whether the MIME type starts with "prefix", e.g. "image/" for any image, compared without
+    /// regard to case. Files whose provider reports no MIME type never match.
+    pub fn is_mime(&self, prefix: &str) -> bool {
+        mime_has_prefix(&self.mime_type, prefix)
+    }
+
+    /// Whether the document can be read. For listed entries this follows from the listing
+    /// itself, otherwise DocumentFile.canRead() is asked, which checks the app's permission.
+    pub fn can_read(&self) -> bool {
+        match self.flags {
+            Some(_) => !self.mime_type.is_empty(),
+            None => self.document_file_check("canRead"),
+        }
+    }
+
+    /// Whether the document can be written (or, for a directory, have children created), by the
+    /// rules of DocumentFile.canWrite(). For listed entries the COLUMN_FLAGS of the listing are
+    /// used, which describe what the provider allows, not whether the grant is read-only.
+    pub fn can_write(&self) -> bool {
+        let Some(flags) = self.flags else {
+            return self.document_file_check("canWrite");
+        };
+        // Deletable documents count as writable, as in DocumentFile
+        if flags.supports_delete() {
+            return true;
+        }
+        if self.is_dir {
+            flags.dir_supports_create()
+        } else {
+            !self.mime_type.is_empty() && flags.supports_write()
+        }
+    }
+
+    /// Whether the provider allows deleting the document (FLAG_SUPPORTS_DELETE).
+    pub fn can_delete(&self) -> bool {
+        self.document_flags().supports_delete()
+    }
+
+    /// Whether the document is virtual (FLAG_VIRTUAL_DOCUMENT), e.g. a Google Docs document
+    /// exposed by Drive. Virtual documents have no byte representation of their own, so `open`
+    /// fails on them; open them with `open_as` and one of their `stream_types` instead.
+    pub fn is_virtual(&self) -> bool {
+        self.document_flags().is_virtual()
+    }
+
+    /// Whether the provider can generate a thumbnail of the document (FLAG_SUPPORTS_THUMBNAIL).
+    pub fn supports_thumbnail(&self) -> bool {
+        self.document_flags().supports_thumbnail()
+    }
+
+    /// The COLUMN_FLAGS of the document, from the listing cursor or queried from the provider.
+    /// Failures are logged and reported as no flags.
+    fn document_flags(&self) -> DocumentFlags {
+        if let Some(flags) = self.flags {
+            return flags;
+        }
+        let flags = (|| -> Result<Option<i64>> {
+            let mut env_guard = get_env()?;
+            let env = &mut *env_guard;
+            let context = get_application_context()?;
+            env.with_local_frame(8, |env| {
+                let uri = parse_uri(env, &self.url)?;
+                query_document_long(env, &context, &uri, "COLUMN_FLAGS")
+            })
+        })();
+        match flags {
+            Ok(flags) => DocumentFlags(flags.unwrap_or(0) as i32),
+            Err(e) => {
+                info!("Failed to query the flags of {}: {}", self.url, e);
+                DocumentFlags::default()
+            }
+        }
+    }
+
+    /// Space of the storage the directory lives on: the columns of its provider root, with
+    /// missing values filled in by StatFs on the volume when it is a local one.
+    fn storage_space(&self) -> Result<RootSpace> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        let authority = self.authority()?;
+        let tree_document_id = self.tree_document_id()?;
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        env.with_local_frame(16, |env| -> Result<RootSpace> {
+            let uri = parse_uri(env, &self.url)?;
+            let mut space = query_root_space(env, &context, &uri)?.unwrap_or_default();
+            if space.available.is_none() || space.capacity.is_none() {
+                if let Some(document_id) = &tree_document_id {
+                    if let Some(volume) = query_volume_space(env, &authority, document_id)? {
+                        space.available = space.available.or(volume.available);
+                        space.capacity = space.capacity.or(volume.capacity);
+                    }
+                }
+            }
+            Ok(space)
+        })
+    }
+
+    /// Call a boolean DocumentFile method without arguments. Failures are logged and reported as
+    /// false.
+    fn document_file_check(&self, method: &str) -> bool {
+        let result = get_env().and_then(|mut env_guard| {
+            env_guard
+                .call_method(&self.document_file, method, "()Z", &[])?
+                .z()
+        });
+        result.unwrap_or_else(|e| {
+            info!("DocumentFile.{} failed for {}: {}", method, self.url, e);
+            false
+        })
+    }
+
+    /// Extension of the file name, without the dot, e.g. "txt" for "notes.txt". Names without a
+    /// dot, ending in a dot, or starting with their only dot (e.g. ".nomedia") have none.
+    pub fn extension(&self) -> Option<&str> {
+        name_extension(&self.filename)
+    }
+
+    /// The file name with its extension replaced by "extension", or removed if "extension" is
+    /// empty, e.g. "notes.md" for "notes.txt" and "md". Names without an extension get one.
+    pub fn with_extension(&self, extension: &str) -> String {
+        let stem = match name_extension(&self.filename) {
+            Some(current) => &self.filename[..self.filename.len() - current.len() - 1],
+            None => &self.filename,
+        };
+        if extension.is_empty() {
+            stem.to_string()
+        } else {
+            format!("{}.{}", stem, extension)
+        }
+    }
+}
+
+/// Look up MimeTypeMap.getSingleton() and call "method" (getExtensionFromMimeType or
+/// getMimeTypeFromExtension) on it, returning None for unknown values.
+fn mime_type_map_lookup(env: &mut JNIEnv, method: &str, value: &str) -> Result<Option<String>> {
+    let mime_type_map = env
+        .call_static_method(
+            "android/webkit/MimeTypeMap",
+            "getSingleton",
+            "()Landroid/webkit/MimeTypeMap;",
+            &[],
+        )?
+        .l()?;
+    let value = env.new_string(value)?;
+    let result = env
+        .call_method(
+            &mime_type_map,
+            method,
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            &[JValueGen::Object(&value)],
+        )?
+        .l()?;
+    if result.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(jstring_to_string(env, result)?))
+}
+
+/// Guess the MIME type of a file from the extension of its name with MimeTypeMap, e.g. for
+/// files created from archive entries. Unknown extensions give "application/octet-stream".
+#[cfg(feature = "zip")]
+pub(crate) fn mime_type_for_name(name: &str) -> Result<String> {
+    let Some(extension) = name_extension(name) else {
+        return Ok(MIME_TYPE_BINARY.to_string());
+    };
+
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+
+    let mime_type = env.with_local_frame(8, |env| {
+        mime_type_map_lookup(env, "getMimeTypeFromExtension", &extension.to_lowercase())
+    })?;
+    Ok(mime_type.unwrap_or_else(|| MIME_TYPE_BINARY.to_string()))
+}
+
+/// Whether "mime_type" starts with "prefix", see `AndroidFile::is_mime`.
+fn mime_has_prefix(mime_type: &str, prefix: &str) -> bool {
+    mime_type
+        .get(..prefix.len())
+        .is_some_and(|start| !start.is_empty() && start.eq_ignore_ascii_case(prefix))
+}
+
+/// Whether "name" contains "pattern", ignoring case, see `AndroidFileOps::list_with_name_like`.
+/// ASCII patterns ignore ASCII case only, like SQL LIKE, so the result matches that of providers
+/// honoring the LIKE selection; other patterns ignore Unicode case.
+fn name_contains(name: &str, pattern: &str) -> bool {
+    if pattern.is_ascii() {
+        name.to_ascii_lowercase()
+            .contains(&pattern.to_ascii_lowercase())
+    } else {
+        name.to_lowercase().contains(&pattern.to_lowercase())
+    }
+}
+
+/// Extension of a file name, see `AndroidFile::extension`.
+fn name_extension(name: &str) -> Option<&str> {
+    match name.rfind('.') {
+        Some(0) | None => None,
+        Some(dot) if dot + 1 == name.len() => None,
+        Some(dot) => Some(&name[dot + 1..]),
+    }
+}
+
+/// Replace the characters providers reject in display names (those invalid on FAT filesystems)
+/// with underscores, see `AndroidFileOps::preview_created_name`.
+fn sanitize_display_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '"' | '*' | '/' | ':' | '<' | '>' | '?' | '\\' | '|' | '\x7f' => '_',
+            c if c < ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// The "counter"-th candidate name tried for a new file, e.g. "notes (2).txt". Counter 0 is the
+/// name itself.
+fn numbered_name(base: &str, extension: Option<&str>, counter: u32) -> String {
+    let base = match counter {
+        0 => base.to_string(),
+        counter => format!("{} ({})", base, counter),
+    };
+    match extension {
+        Some(extension) => format!("{}.{}", base, extension),
+        None => base,
+    }
+}
+
+impl AndroidFileOps for AndroidFile {
+    /// Open the file represented by the AndroidFile object with the specified open mode.
+    /// The "open_mode" str corresponds to that in Android ContentResolver.openFileDescriptor method,
+    /// which can be "r", "w", "wt", "wa", "rw" or "rwt". Please note that there is <b>no</b> standard
+    /// behavior for each mode, and the behavior may vary among different Android versions and file
+    /// providers. For example, "w" may truncate the file or not, so it is recommended to specify the
+    /// mode explicitly.
+    /// Furthermore, "rw" mode requires an on-disk file that supports seeking, while "r" mode and "w"
+    /// mode can be used to read or write to a pipe or socket, respectively.
+    fn open(&self, open_mode: &str) -> Result<File> {
+        // No, you would not want to use this method to open a directory
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        open_content_url(&self.url, open_mode)
+    }
+
+    /// Open the file like `open` and wrap it in a 64 KiB read buffer, so many small reads (e.g.
+    /// reading text line by line) do not each become a read on the provider descriptor. <br />
+    fn open_buffered(&self, open_mode: &str) -> Result<BufReader<File>> {
+        Ok(BufReader::with_capacity(
+            BUFFERED_CAPACITY,
+            self.open(open_mode)?,
+        ))
+    }
+
+    /// Create a new file in the directory represented by the AndroidFile object, see
+    /// `create_file`, and open it for writing with a 64 KiB write buffer. The buffer is written
+    /// out when it is full, and when the writer is flushed or dropped; errors on drop are
+    /// ignored, so call `flush` to make sure all data landed. <br />
+    fn create_buffered(&self, mime_type: &str, file_name: &str) -> Result<BufWriter<File>> {
+        let file = self.create_file(mime_type, file_name)?;
+        Ok(BufWriter::with_capacity(BUFFERED_CAPACITY, file.open("w")?))
+    }
+
+    /// List files in the directory represented by the AndroidFile object. If the object does not
+    /// represent a tree directory, an error will be returned.
+    fn list_files(&self) -> Result<Vec<AndroidFile>> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Listing files in directory: {}", self.url);
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        list_children(env, &context, self, SortOrder::NameAsc, None)
+    }
+
+    /// List files like `list_files`, in the given order. Orders other than `SortOrder::NameAsc`
+    /// are passed to the provider's query, which is retried without one if the provider rejects
+    /// it, and every order is applied in memory as well, since most providers ignore it. <br />
+    /// PARAMS: "order" - The order of the entries; `SortOrder::NameAsc` is that of `list_files`.
+    /// <br />
+    fn list_files_sorted(&self, order: SortOrder) -> Result<Vec<AndroidFile>> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Listing files in directory: {} ({:?})", self.url, order);
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        list_children(env, &context, self, order, None)
+    }
+
+    /// List the directory like `list_files`, aborting with a Cancelled error once "cancellation"
+    /// is cancelled. The children query is aborted through its CancellationSignal. <br />
+    fn list_files_with_cancellation(
+        &self,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<AndroidFile>> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Listing files in directory: {} (cancellable)", self.url);
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        list_children(env, &context, self, SortOrder::NameAsc, Some(cancellation))
+    }
+
+    /// Re-read the metadata of the document from the provider, e.g. after another app modified
+    /// it. The fields of an AndroidFile object are a snapshot taken when it was created; this
+    /// queries them again through the DocumentFile reference it holds, without resolving the URL
+    /// again. Flags are queried lazily afterwards. <br />
+    /// RETURNS: An updated copy of the AndroidFile object, or a NotFound error if the document
+    /// no longer exists. <br />
+    fn refresh(&self) -> Result<AndroidFile> {
+        if !self.exists()? {
+            return Err(SafError::NotFound(format!(
+                "The document {} no longer exists",
+                self.url
+            )));
+        }
+
+        from_document_file(self.document_file.as_obj())
+    }
+
+    /// Get notified when the directory represented by the AndroidFile object changes, e.g. to
+    /// keep a file manager view live instead of polling `list_files`. A ContentObserver is
+    /// registered on the children URI of the directory, or on the document URI of a file. The
+    /// app must ship the SafContentObserver class, see the README. <br />
+    /// Note that "cb" runs on a binder thread, not the main thread, one call at a time. It
+    /// receives no details, should return quickly, and must not drop the returned handle from
+    /// within. Providers may coalesce notifications, and some never send any. <br />
+    /// PARAMS: "cb" - Called after each change notification. <br />
+    /// RETURNS: A handle that keeps the observer registered until it is dropped. <br />
+    fn watch<F: Fn() + Send + 'static>(&self, cb: F) -> Result<WatchHandle> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        env.with_local_frame(16, |env| -> Result<WatchHandle> {
+            let uri = parse_uri(env, &self.url)?;
+            let uri = if self.is_dir {
+                let document_id = env
+                    .call_static_method(
+                        "android/provider/DocumentsContract",
+                        "getDocumentId",
+                        "(Landroid/net/Uri;)Ljava/lang/String;",
+                        &[JValueGen::Object(&uri)],
                     )?
                     .l()?;
+                env.call_static_method(
+                    "android/provider/DocumentsContract",
+                    "buildChildDocumentsUriUsingTree",
+                    "(Landroid/net/Uri;Ljava/lang/String;)Landroid/net/Uri;",
+                    &[JValueGen::Object(&uri), JValueGen::Object(&document_id)],
+                )?
+                .l()?
+            } else {
+                uri
+            };
+            watch_uri(env, &context, &uri, Box::new(cb))
+        })
+    }
+
+    /// Create a new file in the directory represented by the AndroidFile object.
+    /// If self does not represent a directory, an error will be returned. <br />
+    /// PARAMS: MIME type and file name.
+    /// The MIME type should be a valid MIME type string, and the file name should not contain any
+    /// path separator. When MIME type and extension in file name mismatch, a correct extension will
+    /// be appended (thus it is recommended not to include extension).
+    /// When names collide, a number will be appended. <br />
+    /// RETURNS: A new AndroidFile object representing the newly created file. <br />
+    fn create_file(&self, mime_type: &str, file_name: &str) -> Result<AndroidFile> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!(
+            "Creating file named {} with MIME type {} in directory: {}",
+            file_name, mime_type, self.url
+        );
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        // Convert MIME type and file name to Java strings
+        let mime_type_str = env.new_string(mime_type)?;
+        let file_name_str = env.new_string(file_name)?;
+
+        // Create a new file in the directory
+        let new_file = env.call_method(
+            &self.document_file,
+            "createFile",
+            "(Ljava/lang/String;Ljava/lang/String;)Landroidx/documentfile/provider/DocumentFile;",
+            &[
+                JValueGen::Object(&mime_type_str),
+                JValueGen::Object(&file_name_str),
+            ],
+        );
+        check_exception(env)?;
+        let new_file = new_file?.l()?;
+
+        from_document_file(&new_file)
+    }
+
+    /// Create a new directory in the directory represented by the AndroidFile object.
+    /// If self does not represent a directory, an error will be returned. <br />
+    /// PARAMS: Directory name. When names collide, the file name will be appended with a number. <br />
+    /// RETURNS: A new AndroidFile object representing the newly created directory. <br />
+    fn create_directory(&self, dir_name: &str) -> Result<AndroidFile> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!(
+            "Creating directory named {} in directory: {}",
+            dir_name, self.url
+        );
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        // Convert directory name to Java string
+        let file_name_str = env.new_string(dir_name)?;
+
+        // Create a new file in the directory
+        let new_dir = env
+            .call_method(
+                &self.document_file,
+                "createDirectory",
+                "(Ljava/lang/String;)Landroidx/documentfile/provider/DocumentFile;",
+                &[JValueGen::Object(&file_name_str)],
+            )?
+            .l()?;
+
+        from_document_file(&new_dir)
+    }
+
+    /// Remove the file or directory represented by the AndroidFile object. If the object represents
+    /// a directory, the directory will be removed recursively. The method will return true if the
+    /// file or directory is removed successfully, or false if the file or directory does not exist.
+    /// Note that false is also returned when the provider refuses the delete; use `delete` to
+    /// tell the two apart.
+    fn remove_file(&self) -> Result<bool> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        // Delete the file or directory
+        let result = env
+            .call_method(self.document_file.as_obj(), "delete", "()Z", &[])?
+            .z()?;
+
+        Ok(result)
+    }
+
+    /// Delete the file or directory represented by the AndroidFile object, like `remove_file`,
+    /// but report why a delete did not happen. Whether a directory is removed with its content
+    /// depends on the provider; use `delete_recursive` to be sure. <br />
+    /// RETURNS: A NotFound error if the document does not exist, a DeleteFailed error if the
+    /// provider refused to delete it, or the exception the provider threw. <br />
+    fn delete(&self) -> Result<()> {
+        let deleted = {
+            // Obtain JNIEnv using improved get_env function
+            let mut env_guard = get_env()?;
+            let env = &mut *env_guard;
+
+            let deleted = env.call_method(self.document_file.as_obj(), "delete", "()Z", &[]);
+            check_exception(env)?;
+            deleted?.z()?
+        };
+        if deleted {
+            return Ok(());
+        }
+
+        if !self.exists()? {
+            return Err(SafError::NotFound(format!("{} does not exist", self.url)));
+        }
+        Err(SafError::DeleteFailed(format!(
+            "The provider refused to delete {}",
+            self.url
+        )))
+    }
+
+    /// Delete the file or directory represented by the AndroidFile object. Directories are
+    /// emptied entry by entry, depth first, before they are deleted themselves, so this works on
+    /// providers that only delete empty directories. The first failure stops the delete; entries
+    /// removed before it stay removed. <br />
+    /// RETURNS: The number of files and directories removed, including this one. <br />
+    fn delete_recursive(&self) -> Result<usize> {
+        let mut removed = 0;
+        if self.is_dir {
+            for child in self.list_files()? {
+                removed += child.delete_recursive()?;
+            }
+        }
+        self.delete()?;
+        Ok(removed + 1)
+    }
+
+    /// Get the plain document URI of the object, in the form returned by
+    /// DocumentsContract.buildDocumentUri(authority, documentId). Unlike `url`, which is usually
+    /// scoped to the granted tree, this URI does not contain the tree part, which is what some
+    /// provider methods (e.g. openTypedAssetFileDescriptor on certain providers) expect.
+    fn document_url(&self) -> Result<String> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        let uri = parse_uri(env, &self.url)?;
+        let authority = env
+            .call_method(&uri, "getAuthority", "()Ljava/lang/String;", &[])?
+            .l()?;
+        let document_id = env
+            .call_static_method(
+                "android/provider/DocumentsContract",
+                "getDocumentId",
+                "(Landroid/net/Uri;)Ljava/lang/String;",
+                &[JValueGen::Object(&uri)],
+            )?
+            .l()?;
+
+        // Build the document URI without the tree part
+        let document_uri = env
+            .call_static_method(
+                "android/provider/DocumentsContract",
+                "buildDocumentUri",
+                "(Ljava/lang/String;Ljava/lang/String;)Landroid/net/Uri;",
+                &[
+                    JValueGen::Object(&authority),
+                    JValueGen::Object(&document_id),
+                ],
+            )?
+            .l()?;
+
+        object_to_string(env, &document_uri)
+    }
+
+    /// Find files and directories whose names match a glob pattern in the directory represented
+    /// by the AndroidFile object. If self does not represent a directory, an error will be
+    /// returned. <br />
+    /// PARAMS: The pattern supports `*` (any sequence), `?` (any single character) and character
+    /// classes like `[abc]`, `[a-z]` or `[!0-9]`, and is matched against the whole display name.
+    /// When "recursive" is true, all subdirectories are searched as well. <br />
+    /// RETURNS: Matching entries, in the order they are found (depth-first, sorted by name within
+    /// each directory). <br />
+    fn find(&self, pattern: &str, recursive: bool) -> Result<Vec<AndroidFile>> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!(
+            "Finding files matching {} in directory: {} (recursive: {})",
+            pattern, self.url, recursive
+        );
+
+        let mut matches = Vec::new();
+        let mut pending = vec![self.clone()];
+        while let Some(dir) = pending.pop() {
+            let children = dir.list_files()?;
+            // Push subdirectories in reverse, so they are visited in name order
+            if recursive {
+                pending.extend(children.iter().rev().filter(|c| c.is_dir).cloned());
+            }
+            matches.extend(
+                children
+                    .into_iter()
+                    .filter(|child| glob_match(pattern, &child.filename)),
+            );
+        }
+
+        Ok(matches)
+    }
+
+    /// Create a new file in the directory represented by the AndroidFile object and fill it with
+    /// the remaining content of an already opened file descriptor (e.g. one returned by `open` on
+    /// another document). Naming follows `create_file`. <br />
+    /// When both descriptors are regular files on local storage, the data is copied in-kernel
+    /// (copy_file_range/sendfile); otherwise it is streamed in chunks through userspace, so large
+    /// files are never buffered entirely in memory. If the copy fails, the new file is removed
+    /// again. <br />
+    /// RETURNS: A new AndroidFile object representing the newly created file. <br />
+    fn create_file_from(
+        &self,
+        mime_type: &str,
+        file_name: &str,
+        source: &mut File,
+    ) -> Result<AndroidFile> {
+        create_filled(self, mime_type, file_name, source, None)
+    }
+
+    /// Copy a local file (e.g. a download in app-private storage) into a new file in the
+    /// directory represented by the AndroidFile object, see `create_file_from`. The source is
+    /// opened before anything is created, so a missing source leaves the directory untouched.
+    /// <br />
+    /// RETURNS: A new AndroidFile object representing the newly created file, or a NotFound
+    /// error if "src" does not exist. <br />
+    fn import_from_path(&self, src: &Path, mime_type: &str, new_name: &str) -> Result<AndroidFile> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        let mut source = File::open(src).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                SafError::NotFound(format!("{} does not exist", src.display()))
+            }
+            _ => e.into(),
+        })?;
+        if source.metadata()?.is_dir() {
+            return Err(SafError::Other(format!("{} is a directory", src.display())));
+        }
+
+        self.create_file_from(mime_type, new_name, &mut source)
+    }
+
+    /// Copy the file represented by the AndroidFile object to a local path, e.g. into the cache
+    /// directory for a library that only accepts paths. Missing parent directories of "dest" are
+    /// created, and an existing file at "dest" is replaced. <br />
+    /// RETURNS: The number of bytes written. <br />
+    fn export_to_path(&self, dest: &Path) -> Result<u64> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        let mut source = self.open("r")?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut destination = File::create(dest)?;
+        let copied = copy_file_contents(&mut source, &mut destination)?;
+        info!(
+            "Copied {} bytes from {} to {}",
+            copied,
+            self.url,
+            dest.display()
+        );
+
+        Ok(copied)
+    }
+
+    /// Read at most "len" bytes from the start of the file. Fewer bytes are returned when the
+    /// file is shorter. The descriptor is closed before returning.
+    fn read_prefix(&self, len: usize) -> Result<Vec<u8>> {
+        let file = self.open("r")?;
+        let mut prefix = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut prefix)?;
+        Ok(prefix)
+    }
+
+    /// Read at most "len" bytes starting at byte "offset", e.g. to resume a download or inspect
+    /// a header, without reading the bytes before it. Fewer bytes are returned when the file ends
+    /// earlier, none if "offset" is past its end. The descriptor is closed before returning. <br />
+    /// RETURNS: A NotSeekable error if the provider serves the document through a pipe or
+    /// socket, rather than silently reading from the start. <br />
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut file = self.open("r")?;
+        let not_seekable =
+            || SafError::NotSeekable(format!("The descriptor of {} is not seekable", self.url));
+        if !file.metadata()?.file_type().is_file() {
+            return Err(not_seekable());
+        }
+        if let Err(e) = file.seek(SeekFrom::Start(offset)) {
+            return Err(match e.raw_os_error() {
+                Some(libc::ESPIPE) => not_seekable(),
+                _ => e.into(),
+            });
+        }
+
+        let mut range = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut range)?;
+        Ok(range)
+    }
+
+    /// Check whether the provider serves the document through a seekable descriptor, to choose
+    /// between random-access and streaming code paths before starting an operation that needs to
+    /// seek. The file is opened for reading and a zero-offset seek is attempted; pipes and sockets
+    /// fail it. The descriptor is closed before returning. <br />
+    /// RETURNS: true if the seek succeeded, false otherwise. <br />
+    fn is_seekable(&self) -> Result<bool> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+        let mut file = self.open("r")?;
+        Ok(file.stream_position().is_ok())
+    }
 
-                // Get path and url from child URI
-                let path_object = env
-                    .call_method(&child_uri, "getPath", "()Ljava/lang/String;", &[])?
-                    .l()?;
-                let path = env
-                    .get_string(&JString::from(path_object))?
-                    .to_string_lossy()
-                    .into_owned();
-                let url = env
-                    .call_method(&child_uri, "toString", "()Ljava/lang/String;", &[])?
-                    .l()
-                    .and_then(|url| {
-                        env.get_string(&JString::from(url))
-                            .map(|s| s.to_string_lossy().into_owned())
+    /// Read the file from start to end in chunks of "chunk_size" bytes and pass each chunk to
+    /// "cb", e.g. to upload it while driving a progress bar, without holding more than one chunk
+    /// in memory. Every chunk is full except the last one. A "chunk_size" of 0 is treated as 1.
+    /// <br />
+    /// PARAMS: "cb" - Called with each chunk; returning an error stops reading. <br />
+    /// RETURNS: The total number of bytes read, or the first error returned by "cb". <br />
+    fn read_chunks<F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        chunk_size: usize,
+        mut cb: F,
+    ) -> Result<u64> {
+        let mut file = self.open("r")?;
+        let mut chunk = vec![0u8; chunk_size.max(1)];
+        let mut total = 0u64;
+        loop {
+            // Fill the chunk, reads on a provider descriptor may return fewer bytes
+            let mut filled = 0;
+            while filled < chunk.len() {
+                match file.read(&mut chunk[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if filled == 0 {
+                return Ok(total);
+            }
+            cb(&chunk[..filled])?;
+            total += filled as u64;
+            if filled < chunk.len() {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Guess the text encoding of the file from its first few kilobytes. Byte order marks
+    /// (UTF-8/16/32) are definitive; otherwise a heuristic tells valid UTF-8 from UTF-16 without
+    /// BOM, single-byte encodings (reported as Latin-1) and binary content. The returned
+    /// confidence is a rough indication only, e.g. pure ASCII is reported as UTF-8 with a
+    /// moderate confidence since it is equally valid in any ASCII-compatible encoding.
+    fn detect_encoding(&self) -> Result<EncodingGuess> {
+        let prefix = self.read_prefix(ENCODING_PREFIX_LEN)?;
+        Ok(guess_encoding(&prefix))
+    }
+
+    /// Create a new file like `create_file`, but fail if an entry with the same name already
+    /// exists instead of letting the provider append a number (O_EXCL-like semantics, e.g. for
+    /// lock files). An extension appended because of a MIME type mismatch is still allowed. <br />
+    /// Note that the existence check and the creation are two separate provider calls. If another
+    /// writer wins the race in between and the provider renames our file, the file is removed
+    /// again and an error is returned. <br />
+    /// RETURNS: A new AndroidFile object representing the newly created file. <br />
+    fn create_file_exclusive(&self, mime_type: &str, file_name: &str) -> Result<AndroidFile> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        {
+            // Obtain JNIEnv using improved get_env function
+            let mut env_guard = get_env()?;
+            let env = &mut *env_guard;
+
+            if find_child_document(env, self.document_file.as_obj(), file_name)?.is_some() {
+                return Err(SafError::AlreadyExists(format!(
+                    "A file named {} already exists in the directory",
+                    file_name
+                )));
+            }
+        }
+
+        let created = self.create_file(mime_type, file_name)?;
+        let name_kept = created.filename == file_name
+            || created
+                .filename
+                .strip_prefix(file_name)
+                .is_some_and(|rest| rest.starts_with('.') && !rest.contains(' '));
+        if !name_kept {
+            info!(
+                "File {} was created as {}, removing it again",
+                file_name, created.filename
+            );
+            created.remove_file()?;
+            return Err(SafError::AlreadyExists(format!(
+                "A file named {} already exists in the directory",
+                file_name
+            )));
+        }
+
+        Ok(created)
+    }
+
+    /// Measure how long the provider takes to open the file, serve a single small read and close
+    /// the descriptor. This is a diagnostic heuristic rather than a precise benchmark: a single
+    /// sample is affected by caching and system load, but it is good enough to tell a slow cloud
+    /// provider (hundreds of milliseconds) from local storage (a few milliseconds), e.g. to decide
+    /// how aggressively to prefetch or buffer.
+    fn probe_latency(&self) -> Result<Duration> {
+        let start = Instant::now();
+        {
+            let mut file = self.open("r")?;
+            let mut buffer = [0u8; LATENCY_PROBE_LEN];
+            let _ = file.read(&mut buffer)?;
+        }
+        let elapsed = start.elapsed();
+        info!("Provider latency for {}: {:?}", self.url, elapsed);
+
+        Ok(elapsed)
+    }
+
+    /// List files in the directory like `list_files`, additionally returning the capability flags
+    /// of every entry. They are read in the same cursor pass, so this is much cheaper than
+    /// querying each entry separately. Directories on the filesystem (see
+    /// `app_external_directory`) have no provider, and their entries get empty flags.
+    fn list_files_detailed(&self) -> Result<Vec<DetailedFile>> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Listing files with details in directory: {}", self.url);
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        if is_file_url(&self.url) {
+            let mut files: Vec<DetailedFile> = list_filesystem_children(env, self)?
+                .into_iter()
+                .map(|file| DetailedFile {
+                    file,
+                    flags: DocumentFlags::default(),
+                })
+                .collect();
+            files.sort_by(|a, b| a.file.sort_key().cmp(&b.file.sort_key()));
+            return Ok(files);
+        }
+
+        let context = get_application_context()?;
+        let parent_uri = parse_uri(env, &self.url)?;
+        let tree_urls = TreeUrls::of(env, &parent_uri)?;
+
+        let mut files = Vec::new();
+        for_each_child_row(env, &context, &parent_uri, |env, row| {
+            let file = child_from_row(
+                env,
+                &context,
+                self.document_file.as_obj(),
+                &parent_uri,
+                &tree_urls,
+                &row,
+            )?;
+            files.push(DetailedFile {
+                file,
+                flags: DocumentFlags(row.flags),
+            });
+            Ok(())
+        })?;
+
+        // Sort files by name
+        files.sort_by(|a, b| a.file.sort_key().cmp(&b.file.sort_key()));
+
+        Ok(files)
+    }
+
+    /// Move the media file represented by the AndroidFile object to the system trash, from which
+    /// it can be restored with `restore_from_trash` until the system purges it (usually after
+    /// 30 days). Only media documents (images, video, audio, downloads) backed by MediaStore are
+    /// supported, and only on Android 11 (API 30) and above; other documents return an error.
+    /// Note that MediaStore only lets the app trash items it owns or has write access to; items of
+    /// other apps require MediaStore.createTrashRequest, which needs an Activity for user consent.
+    fn move_to_trash(&self) -> Result<()> {
+        info!("Moving to trash: {}", self.url);
+        set_trashed(self, true)
+    }
+
+    /// Restore the media file represented by the AndroidFile object from the system trash.
+    /// The same restrictions as for `move_to_trash` apply.
+    fn restore_from_trash(&self) -> Result<()> {
+        info!("Restoring from trash: {}", self.url);
+        set_trashed(self, false)
+    }
+
+    /// Resolve a relative path like "../sibling/file.txt" against the AndroidFile object. "."
+    /// and empty segments are ignored, ".." moves to the parent directory and any other segment
+    /// is looked up by display name in the current directory. Navigating above the root of the
+    /// granted tree is rejected with an EscapesTree error rather than silently stopping at the
+    /// root. <br />
+    /// RETURNS: The AndroidFile object the path points to. An error is returned if a segment does
+    /// not exist, or if a segment other than the last one is not a directory. <br />
+    fn navigate(&self, relative_path: &str) -> Result<AndroidFile> {
+        info!("Navigating to {} from {}", relative_path, self.url);
+
+        // Directories visited so far, so ".." can go back without asking the provider
+        let mut ancestors = Vec::new();
+        let mut current = self.clone();
+        for segment in relative_path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    current = match ancestors.pop() {
+                        Some(parent) => parent,
+                        None => parent_document(&current)?.ok_or_else(|| {
+                            SafError::EscapesTree(format!(
+                                "Path {} escapes the granted tree",
+                                relative_path
+                            ))
+                        })?,
+                    };
+                }
+                name => {
+                    if !current.is_dir {
+                        return Err(SafError::NotADirectory);
+                    }
+                    let child = child_document(&current, name)?.ok_or_else(|| {
+                        SafError::NotFound(format!(
+                            "{} does not exist in {}",
+                            name, current.filename
+                        ))
                     })?;
+                    ancestors.push(std::mem::replace(&mut current, child));
+                }
+            }
+        }
 
-                // Check if it's a directory
-                let is_dir = env
-                    .call_method(
-                        &mime_type_jstr,
-                        "equals",
-                        "(Ljava/lang/Object;)Z",
-                        &[JValueGen::Object(&mime_type_dir)],
-                    )?
-                    .z()?;
+        Ok(current)
+    }
 
-                // Create DocumentFile object
-                let document_file_class = "androidx/documentfile/provider/DocumentFile";
-                let document_file = env
-                    .call_static_method(
-                        document_file_class,
-                        "fromSingleUri",
-                        "(Landroid/content/Context;Landroid/net/Uri;)Landroidx/documentfile/provider/DocumentFile;",
-                        &[JValueGen::Object(context.as_obj()), JValueGen::Object(&child_uri)],
-                    )?
+    /// Get the directory containing the file or directory represented by the AndroidFile object,
+    /// e.g. to create a sibling file next to an opened document. The parent is resolved like
+    /// DocumentFile.getParentFile, but also works for objects that were not reached by walking
+    /// down from the tree root. <br />
+    /// RETURNS: The parent directory, or None at the root of the granted tree and for single
+    /// documents (e.g. from `from_single_uri`), which have no parent. <br />
+    fn parent(&self) -> Result<Option<AndroidFile>> {
+        parent_document(self)
+    }
+
+    /// Get a thumbnail of the document generated by the provider, e.g. for a gallery, with
+    /// DocumentsContract.getDocumentThumbnail, encoded as PNG. This avoids transferring and
+    /// decoding full-size images or videos. The provider picks the exact size, usually close to
+    /// the requested one, keeping the aspect ratio. <br />
+    /// PARAMS: "width" and "height" - The requested size in pixels. <br />
+    /// RETURNS: The PNG-encoded thumbnail, or an Unsupported error if the document does not
+    /// have FLAG_SUPPORTS_THUMBNAIL. <br />
+    fn thumbnail(&self, width: i32, height: i32) -> Result<Vec<u8>> {
+        if !self.supports_thumbnail() {
+            return Err(SafError::Unsupported(format!(
+                "The provider has no thumbnails for {}",
+                self.url
+            )));
+        }
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        env.with_local_frame(16, |env| -> Result<Vec<u8>> {
+            let uri = parse_uri(env, &self.url)?;
+            let content_resolver = content_resolver(env, &context)?;
+            let size = env.new_object(
+                "android/graphics/Point",
+                "(II)V",
+                &[JValueGen::Int(width), JValueGen::Int(height)],
+            )?;
+            let bitmap = env.call_static_method(
+                "android/provider/DocumentsContract",
+                "getDocumentThumbnail",
+                "(Landroid/content/ContentResolver;Landroid/net/Uri;Landroid/graphics/Point;Landroid/os/CancellationSignal;)Landroid/graphics/Bitmap;",
+                &[
+                    JValueGen::Object(&content_resolver),
+                    JValueGen::Object(&uri),
+                    JValueGen::Object(&size),
+                    JValueGen::Object(&JObject::null()),
+                ],
+            );
+            check_exception(env)?;
+            let bitmap = bitmap?.l()?;
+            if bitmap.is_null() {
+                return Err(SafError::NullDocument(format!(
+                    "The provider returned no thumbnail for {}",
+                    self.url
+                )));
+            }
+
+            // Encode the bitmap as PNG, then free its pixels right away
+            let format = env
+                .get_static_field(
+                    "android/graphics/Bitmap$CompressFormat",
+                    "PNG",
+                    "Landroid/graphics/Bitmap$CompressFormat;",
+                )?
+                .l()?;
+            let stream = env.new_object("java/io/ByteArrayOutputStream", "()V", &[])?;
+            let compressed = env
+                .call_method(
+                    &bitmap,
+                    "compress",
+                    "(Landroid/graphics/Bitmap$CompressFormat;ILjava/io/OutputStream;)Z",
+                    &[
+                        JValueGen::Object(&format),
+                        JValueGen::Int(100),
+                        JValueGen::Object(&stream),
+                    ],
+                )?
+                .z()?;
+            env.call_method(&bitmap, "recycle", "()V", &[])?.v()?;
+            if !compressed {
+                return Err(SafError::Other(format!(
+                    "Failed to encode the thumbnail of {}",
+                    self.url
+                )));
+            }
+
+            let bytes = JByteArray::from(
+                env.call_method(&stream, "toByteArray", "()[B", &[])?
+                    .l()?,
+            );
+            Ok(env.convert_byte_array(&bytes)?)
+        })
+    }
+
+    /// Open the document converted to "mime_type" with
+    /// ContentResolver.openTypedAssetFileDescriptor, e.g. to export a virtual Google Docs
+    /// document as "application/pdf". Regular documents can be opened this way too, when
+    /// "mime_type" matches their own type. The descriptor is read-only and usually a pipe. <br />
+    /// PARAMS: "mime_type" - The requested type, one of `stream_types`. <br />
+    /// RETURNS: The descriptor, positioned at the start of the converted content, or an error if
+    /// the provider cannot convert the document to that type. <br />
+    fn open_as(&self, mime_type: &str) -> Result<File> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        env.with_local_frame(16, |env| -> Result<File> {
+            let uri = parse_uri(env, &self.url)?;
+            let content_resolver = content_resolver(env, &context)?;
+            let mime_type_str = env.new_string(mime_type)?;
+            let asset_fd = env.call_method(
+                &content_resolver,
+                "openTypedAssetFileDescriptor",
+                "(Landroid/net/Uri;Ljava/lang/String;Landroid/os/Bundle;)Landroid/content/res/AssetFileDescriptor;",
+                &[
+                    JValueGen::Object(&uri),
+                    JValueGen::Object(&mime_type_str),
+                    JValueGen::Object(&JObject::null()),
+                ],
+            );
+            check_exception(env)?;
+            let asset_fd = asset_fd?.l()?;
+            if asset_fd.is_null() {
+                return Err(SafError::NullDocument(format!(
+                    "The provider returned no {} stream for {}",
+                    mime_type, self.url
+                )));
+            }
+
+            // The content may start inside a larger file, as with assets of an APK
+            let start_offset = env
+                .call_method(&asset_fd, "getStartOffset", "()J", &[])?
+                .j()?;
+            let parcel_fd = env
+                .call_method(
+                    &asset_fd,
+                    "getParcelFileDescriptor",
+                    "()Landroid/os/ParcelFileDescriptor;",
+                    &[],
+                )?
+                .l()?;
+            let mut file = file_from_parcel_fd(env, &parcel_fd)?;
+            if start_offset > 0 {
+                file.seek(SeekFrom::Start(start_offset as u64))?;
+            }
+            Ok(file)
+        })
+    }
+
+    /// Get the MIME types the document can be opened as with `open_as`
+    /// (ContentResolver.getStreamTypes), e.g. "application/pdf" and
+    /// "application/vnd.openxmlformats-officedocument.wordprocessingml.document" for a Google
+    /// Docs document. <br />
+    /// RETURNS: The types, empty if the provider offers no conversion. <br />
+    fn stream_types(&self) -> Result<Vec<String>> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        env.with_local_frame(16, |env| -> Result<Vec<String>> {
+            let uri = parse_uri(env, &self.url)?;
+            let content_resolver = content_resolver(env, &context)?;
+            let filter = env.new_string("*/*")?;
+            let types = env.call_method(
+                &content_resolver,
+                "getStreamTypes",
+                "(Landroid/net/Uri;Ljava/lang/String;)[Ljava/lang/String;",
+                &[JValueGen::Object(&uri), JValueGen::Object(&filter)],
+            );
+            check_exception(env)?;
+            let types = JObjectArray::from(types?.l()?);
+            if types.is_null() {
+                return Ok(Vec::new());
+            }
+
+            let count = env.get_array_length(&types)?;
+            let mut stream_types = Vec::with_capacity(count as usize);
+            for index in 0..count {
+                let mime_type = env.get_object_array_element(&types, index)?;
+                stream_types.push(jstring_to_string(env, mime_type)?);
+            }
+            Ok(stream_types)
+        })
+    }
+
+    /// Open the gzip-compressed file represented by the AndroidFile object for reading, returning
+    /// a reader that yields the decompressed content. Only available with the "compression"
+    /// feature.
+    #[cfg(feature = "compression")]
+    fn open_gz_reader(&self) -> Result<GzDecoder<File>> {
+        Ok(GzDecoder::new(self.open("r")?))
+    }
+
+    /// Create a new gzip-compressed file named "file_name" in the directory represented by the
+    /// AndroidFile object, containing "data" compressed with the default level. The MIME type is
+    /// "application/gzip", so the name should include the ".gz" extension. Only available with
+    /// the "compression" feature. <br />
+    /// RETURNS: A new AndroidFile object representing the newly created file. <br />
+    #[cfg(feature = "compression")]
+    fn create_gz_file(&self, file_name: &str, data: &[u8]) -> Result<AndroidFile> {
+        let created = self.create_file("application/gzip", file_name)?;
+        let mut encoder = GzEncoder::new(created.open("wt")?, Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()?;
+
+        Ok(created)
+    }
+
+    /// Get the URI of the tree the AndroidFile object belongs to, i.e. the URI the user granted
+    /// access to, as built by DocumentsContract.buildTreeDocumentUri. For objects that are not
+    /// part of a tree (e.g. single documents), the document URI itself is returned.
+    fn owning_tree_url(&self) -> Result<String> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        let uri = parse_uri(env, &self.url)?;
+        let tree_uri = owning_tree_uri(env, &uri)?;
+        object_to_string(env, &tree_uri)
+    }
+
+    /// Check which access the app holds on the tree the AndroidFile object belongs to, using
+    /// Context.checkUriPermission with the read and write grant flags. This lets an app disable
+    /// editing when only read access was granted, instead of failing deep inside a write. <br />
+    /// RETURNS: PermissionLevel::None, PermissionLevel::Read or PermissionLevel::ReadWrite. A
+    /// write-only grant, which SAF never hands out, is reported as None. <br />
+    fn permission_level(&self) -> Result<PermissionLevel> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        let uri = parse_uri(env, &self.url)?;
+        let tree_uri = owning_tree_uri(env, &uri)?;
+        permission_level_of(env, &context, &tree_uri)
+    }
+
+    /// Open the file for reading and return a reader that writes every byte it reads into
+    /// "cache_writer" as a side effect, e.g. to cache a document locally or hash it while it is
+    /// being consumed, in a single pass over the provider descriptor. An error from the cache
+    /// writer aborts the read, so the copy is never silently incomplete. Use
+    /// `TeeReader::into_inner` to get the writer back, e.g. to flush it.
+    fn open_tee<W: Write>(&self, cache_writer: W) -> Result<TeeReader<File, W>> {
+        Ok(TeeReader::new(self.open("r")?, cache_writer))
+    }
+
+    /// Get the used and total bytes of the storage the AndroidFile object lives on, from the
+    /// available and capacity columns of the provider root it belongs to. This depends heavily on
+    /// the provider: local storage reports both, many cloud providers report only the available
+    /// bytes or nothing at all, and capacity is only defined since Android 10 (API 29). <br />
+    /// RETURNS: The quota, or None if the provider does not report it. <br />
+    fn provider_quota(&self) -> Result<Option<QuotaInfo>> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        let uri = parse_uri(env, &self.url)?;
+        let quota = query_root_space(env, &context, &uri)?.and_then(|space| {
+            let (available, total) = (space.available?, space.capacity?);
+            Some(QuotaInfo {
+                used: total.saturating_sub(available),
+                total,
+            })
+        });
+
+        Ok(quota)
+    }
+
+    /// Get the number of bytes that can still be written to the storage the directory lives on,
+    /// e.g. to check for room before writing a large file. The provider root is asked first;
+    /// for the external storage provider, StatFs on the volume fills in what the root lacks.
+    /// <br />
+    /// RETURNS: The available bytes, or None if the provider (e.g. a network provider) does not
+    /// report them. A NotADirectory error for files. <br />
+    fn available_bytes(&self) -> Result<Option<u64>> {
+        Ok(self.storage_space()?.available)
+    }
+
+    /// Get the total capacity in bytes of the storage the directory lives on, the same way as
+    /// `available_bytes`. <br />
+    /// RETURNS: The capacity, or None if the provider does not report it. A NotADirectory error
+    /// for files. <br />
+    fn total_bytes(&self) -> Result<Option<u64>> {
+        Ok(self.storage_space()?.capacity)
+    }
+
+    /// Resolve the local filesystem path behind the AndroidFile object, if there is one. The file
+    /// is opened for reading and the target of its descriptor in /proc/self/fd is used, but only
+    /// if that path can be opened by the app itself and points to the very same file (same device
+    /// and inode). Documents served from pipes, sockets, memory or app-private storage of another
+    /// app have no such path. <br />
+    /// RETURNS: The path, or None if the document is not backed by a reachable local file. <br />
+    fn filesystem_path(&self) -> Result<Option<PathBuf>> {
+        let file = self.open("r")?;
+        let fd_metadata = file.metadata()?;
+        if !fd_metadata.file_type().is_file() {
+            return Ok(None);
+        }
+
+        let Some(path) = fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd()))
+            .ok()
+            .filter(|path| path.is_absolute())
+        else {
+            return Ok(None);
+        };
+        let same_file = fs::metadata(&path)
+            .map(|metadata| {
+                metadata.dev() == fd_metadata.dev() && metadata.ino() == fd_metadata.ino()
+            })
+            .unwrap_or(false);
+
+        Ok(same_file.then_some(path))
+    }
+
+    /// Open the AndroidFile object as a SQLite database. SQLite needs a seekable file it can lock
+    /// with POSIX record locks, and opens (and creates journals next to) the database by path,
+    /// which a bare "rw" descriptor from `open` does not guarantee. This resolves the local path
+    /// with `filesystem_path` and checks it is a regular file that can be opened for writing and
+    /// supports locking. Pass `SqliteHandle::path` to SQLite and keep the handle alive while the
+    /// database is open. <br />
+    /// RETURNS: An Unsupported error if the document is not backed by a lockable local file,
+    /// e.g. when it lives on a cloud provider; copy it to local storage instead. <br />
+    fn open_sqlite(&self) -> Result<SqliteHandle> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        let path = self.filesystem_path()?.ok_or_else(|| {
+            SafError::Unsupported(
+                "The document is not backed by a local file, SQLite cannot open it safely"
+                    .to_string(),
+            )
+        })?;
+        open_sqlite_path(path)
+    }
+
+    /// Get the path of the AndroidFile object relative to "ancestor", e.g. "Album/song.mp3" for a
+    /// file below "Music". Document IDs are compared first: for providers with path-like IDs,
+    /// such as the external storage provider ("primary:Music/Album/song.mp3"), the ancestor's
+    /// document ID is a prefix of self's, followed by a "/" separator. Otherwise (opaque IDs),
+    /// the parents of self are walked up to the tree root and their display names are joined,
+    /// which costs one provider query per level. <br />
+    /// RETURNS: The slash-joined path, an empty string if self is the ancestor itself, or None if
+    /// self is not below "ancestor". <br />
+    fn relative_path_from(&self, ancestor: &AndroidFile) -> Result<Option<String>> {
+        let (document_id, ancestor_id) = {
+            // Obtain JNIEnv using improved get_env function
+            let mut env_guard = get_env()?;
+            let env = &mut *env_guard;
+
+            let uri = parse_uri(env, &self.url)?;
+            let ancestor_uri = parse_uri(env, &ancestor.url)?;
+            let mut authorities = Vec::with_capacity(2);
+            for uri in [&uri, &ancestor_uri] {
+                let authority = env
+                    .call_method(uri, "getAuthority", "()Ljava/lang/String;", &[])?
                     .l()?;
+                authorities.push(if authority.is_null() {
+                    None
+                } else {
+                    Some(jstring_to_string(env, authority)?)
+                });
+            }
+            if authorities[0].is_none() || authorities[0] != authorities[1] {
+                return Ok(None);
+            }
+
+            (
+                document_id_of(env, &uri)?,
+                document_id_of(env, &ancestor_uri)?,
+            )
+        };
+
+        if let Some(suffix) = document_id.strip_prefix(&ancestor_id) {
+            // Root IDs such as "primary:" already end with a separator
+            let relative = if suffix.is_empty() || ancestor_id.ends_with(['/', ':']) {
+                Some(suffix)
+            } else {
+                suffix.strip_prefix('/')
+            };
+            if let Some(relative) = relative {
+                return Ok(Some(relative.trim_matches('/').to_string()));
+            }
+        }
+        if !ancestor.is_dir || ancestor_id.is_empty() {
+            return Ok(None);
+        }
+
+        // Opaque document IDs, walk up from self until the ancestor or the tree root is reached
+        let mut names = vec![self.filename.clone()];
+        let mut current = parent_document(self)?;
+        while let Some(parent) = current {
+            if parent.document_id == ancestor_id {
+                names.reverse();
+                return Ok(Some(names.join("/")));
+            }
+            names.push(parent.filename.clone());
+            current = parent_document(&parent)?;
+        }
+        Ok(None)
+    }
+
+    /// Open the file for writing ("w" mode) and return a writer that passes data to the provider
+    /// in chunks of "chunk_size" bytes. At most one chunk is held in memory: once it is full, it
+    /// is written to the descriptor before the write call returns, so uploading to a slow
+    /// provider blocks the producer (backpressure) instead of buffering without bound, and write
+    /// errors surface immediately. Call `ChunkedWriter::finish` to write the last partial chunk
+    /// and observe its errors. A "chunk_size" of 0 is treated as 1.
+    fn open_chunked_writer(&self, chunk_size: usize) -> Result<ChunkedWriter> {
+        Ok(ChunkedWriter::new(self.open("w")?, chunk_size))
+    }
+
+    /// Check whether the AndroidFile object is a placeholder whose content is not (fully)
+    /// available locally, e.g. a cloud file that has not been downloaded yet. Such documents may
+    /// report a nonzero size from cached metadata while reading yields nothing. The provider's
+    /// FLAG_PARTIAL (API 26+) is checked first; if it is not set but the document reports a
+    /// nonzero size, the first byte is read to verify there is content at all. <br />
+    /// RETURNS: true if the document is a placeholder, false for directories and regular files.
+    /// <br />
+    fn is_placeholder(&self) -> Result<bool> {
+        if self.is_dir {
+            return Ok(false);
+        }
+
+        let flags = {
+            // Obtain JNIEnv using improved get_env function
+            let mut env_guard = get_env()?;
+            let env = &mut *env_guard;
+            let context = get_application_context()?;
+
+            let uri = parse_uri(env, &self.url)?;
+            query_document_long(env, &context, &uri, "COLUMN_FLAGS")?
+        };
+        if flags.is_some_and(|flags| DocumentFlags(flags as i32).is_partial()) {
+            return Ok(true);
+        }
+
+        Ok(self.size > 0 && self.read_prefix(1)?.is_empty())
+    }
+
+    /// Open the file for writing ("w" mode) without detaching the descriptor from its
+    /// ParcelFileDescriptor. `open` detaches it, which skips ParcelFileDescriptor.close(), and
+    /// some providers only commit the content and update the size when that is called, leaving a
+    /// zero-length document otherwise. Call `CommittedWriter::finish` when done writing: it
+    /// closes the ParcelFileDescriptor and re-queries the size. Dropping the writer closes it as
+    /// well, but without reporting errors.
+    fn open_committed_writer(&self) -> Result<CommittedWriter> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        let url = self.url.clone();
+        let parcel_fd = open_parcel_fd(env, &context, &url, "w")?;
+        if parcel_fd.is_null() {
+            return Err(SafError::NullDocument(format!(
+                "The provider returned no descriptor for {}",
+                url
+            )));
+        }
+        let fd = env.call_method(&parcel_fd, "getFd", "()I", &[])?.i()? as RawFd;
+        let parcel_fd = env.new_global_ref(parcel_fd)?;
+
+        Ok(CommittedWriter::new(parcel_fd, fd, url))
+    }
+
+    /// Open the file for reading and return a reader that fails with an UnexpectedEof error when
+    /// it reaches EOF before `size` bytes were delivered, e.g. because a cloud provider truncated
+    /// the content or a sync failed midway. Use `CheckedReader::finish` to drain and check the
+    /// rest of the content explicitly. Some providers report inaccurate sizes,
+    /// which is why this check is opt-in rather than part of `open`.
+    fn open_checked_read(&self) -> Result<CheckedReader<File>> {
+        Ok(CheckedReader::new(self.open("r")?, self.size))
+    }
+
+    /// Rename the AndroidFile object, keeping the current extension when "new_stem" has none,
+    /// e.g. renaming "IMG_001.jpg" with "holiday" gives "holiday.jpg", while "holiday.png" is
+    /// used as is. This keeps users of a file manager from breaking file associations by leaving
+    /// out the extension. Directories are renamed to "new_stem" unchanged. <br />
+    /// RETURNS: An AndroidFile object for the renamed document. Providers may change the URI on
+    /// rename, so the old object should not be used afterwards. <br />
+    fn rename_keep_extension(&self, new_stem: &str) -> Result<AndroidFile> {
+        let new_name = match (self.is_dir, self.extension()) {
+            (false, Some(extension)) if name_extension(new_stem).is_none() => {
+                format!("{}.{}", new_stem, extension)
+            }
+            _ => new_stem.to_string(),
+        };
+
+        self.rename(&new_name)
+    }
+
+    /// Compare the current content of the directory represented by the AndroidFile object with
+    /// an earlier listing of it, so a UI can update only the affected rows. Entries are matched
+    /// by document ID (by URL in filesystem directories, which have none), not by name, and an
+    /// entry counts as changed when its name, size, modification time or type differs. <br />
+    /// RETURNS: The added and changed entries from the current listing and the removed entries
+    /// from "previous", each sorted by name. <br />
+    fn list_diff(&self, previous: &[AndroidFile]) -> Result<ListDiff> {
+        let current = self.list_files_detailed()?;
+
+        let mut previous_by_id = HashMap::with_capacity(previous.len());
+        for file in previous {
+            // Objects built from URIs without a document part carry no ID, ask the platform.
+            // Filesystem entries have none at all and are matched by URL
+            let document_id = if is_file_url(&file.url) {
+                file.url.clone()
+            } else if file.document_id.is_empty() {
+                let mut env_guard = get_env()?;
+                env_guard.with_local_frame(4, |env| -> Result<String> {
+                    let uri = parse_uri(env, &file.url)?;
+                    document_id_of(env, &uri)
+                })?
+            } else {
+                file.document_id.clone()
+            };
+            previous_by_id.insert(document_id, file);
+        }
+
+        let mut diff = ListDiff::default();
+        let mut seen = HashSet::with_capacity(current.len());
+        for entry in current {
+            let key = if is_file_url(&entry.file.url) {
+                &entry.file.url
+            } else {
+                &entry.file.document_id
+            };
+            seen.insert(key.clone());
+            match previous_by_id.get(key) {
+                None => diff.added.push(entry.file),
+                Some(old) => {
+                    let changed = old.filename != entry.file.filename
+                        || old.size != entry.file.size
+                        || old.last_modified != entry.file.last_modified
+                        || old.is_dir != entry.file.is_dir;
+                    if changed {
+                        diff.changed.push(entry.file);
+                    }
+                }
+            }
+        }
+        diff.removed = previous_by_id
+            .into_iter()
+            .filter(|(document_id, _)| !seen.contains(document_id))
+            .map(|(_, file)| file.clone())
+            .collect();
+        diff.removed.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+        Ok(diff)
+    }
+
+    /// Open the file with the given mode (see `open`) and take an exclusive advisory lock
+    /// (flock(LOCK_EX)) on it, waiting until other holders release theirs. The lock is released
+    /// when the LockedFile is dropped. Advisory locks only coordinate cooperating holders that
+    /// lock as well, i.e. other components of the app or other processes using flock on the same
+    /// file; they do not stop anyone from writing. They only work on descriptors backed by local
+    /// files, pipes and sockets handed out by remote providers fail with an Unsupported error.
+    fn open_locked(&self, open_mode: &str) -> Result<LockedFile> {
+        LockedFile::lock(self.open(open_mode)?, true)
+    }
+
+    /// Like `open_locked`, but fails with a WouldBlock error instead of waiting when another
+    /// holder has the file locked.
+    fn try_open_locked(&self, open_mode: &str) -> Result<LockedFile> {
+        LockedFile::lock(self.open(open_mode)?, false)
+    }
+
+    /// Write the subtree of the directory represented by the AndroidFile object as a ZIP archive
+    /// into "dest_file", which is opened with "wt". Every file is streamed into the archive with
+    /// its path relative to self as the entry name, and directories get entries of their own, so
+    /// empty ones are kept. The archive is written as a stream, so "dest_file" does not need to
+    /// be seekable. <br />
+    /// RETURNS: The size of the written archive in bytes. <br />
+    #[cfg(feature = "zip")]
+    fn zip_tree_to(&self, dest_file: &AndroidFile) -> Result<u64> {
+        self.zip_tree_to_with_progress(dest_file, &mut |_, _| true)
+    }
+
+    /// Like `zip_tree_to`, calling "progress" with the entry being archived and the number of
+    /// uncompressed bytes archived so far before every chunk that is read. Returning false from
+    /// "progress" cancels archiving with an error; the partial archive is left in "dest_file".
+    #[cfg(feature = "zip")]
+    fn zip_tree_to_with_progress(
+        &self,
+        dest_file: &AndroidFile,
+        progress: &mut dyn FnMut(&str, u64) -> bool,
+    ) -> Result<u64> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Archiving {} into {}", self.url, dest_file.url);
+
+        zip_tree(self, dest_file.open("wt")?, false, progress)
+    }
+
+    /// Export the directory represented by the AndroidFile object as a ZIP archive into "dest",
+    /// like `zip_tree_to`, but leave out files that cannot be opened and directories that
+    /// cannot be listed (e.g. virtual documents or entries removed meanwhile) instead of
+    /// aborting the whole archive. Skipped entries are logged. <br />
+    /// RETURNS: The size of the written archive in bytes. <br />
+    #[cfg(feature = "zip")]
+    fn zip_to(&self, dest: &AndroidFile) -> Result<u64> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Exporting {} into {}", self.url, dest.url);
+
+        zip_tree(self, dest.open("wt")?, true, &mut |_, _| true)
+    }
+
+    /// Extract the ZIP archive "archive" into the directory represented by the AndroidFile
+    /// object, creating subdirectories as needed and overwriting files that already exist. New
+    /// files get the MIME type guessed from their extension.
+    /// Entries with absolute paths or paths that escape the directory through ".." ("Zip Slip")
+    /// are rejected with an error, which stops the extraction. Archives on seekable descriptors
+    /// are read through their central directory, others are read as a stream. <br />
+    /// RETURNS: The number of extracted files, not counting directories. <br />
+    #[cfg(feature = "zip")]
+    fn unzip_into(&self, archive: &AndroidFile) -> Result<usize> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!("Extracting {} into {}", archive.url, self.url);
+
+        let (extracted, _) = unzip_to_directory(self, archive.open("r")?)?;
+        Ok(extracted)
+    }
+
+    /// Compute the SHA-256 digest of the file content in a single streaming pass, so memory use
+    /// stays bounded for large files. Only available with the "hashing" feature. <br />
+    /// RETURNS: The 32-byte digest. <br />
+    #[cfg(feature = "hashing")]
+    fn sha256(&self) -> Result<[u8; 32]> {
+        sha256_of(self.open("r")?)
+    }
+
+    /// Compute the CRC-32 (IEEE) checksum of the file content in a single streaming pass, a
+    /// cheaper change check than `sha256`. Only available with the "hashing" feature. <br />
+    #[cfg(feature = "hashing")]
+    fn crc32(&self) -> Result<u32> {
+        crc32_of(self.open("r")?)
+    }
+
+    /// Open the file like `open` without blocking the async runtime. Attaching the thread to the
+    /// JVM and opening the descriptor run on tokio's blocking thread pool (spawn_blocking); the
+    /// JNIEnv is obtained and released within that task. Only available with the "tokio" feature.
+    /// <br />
+    /// RETURNS: The descriptor as a tokio File. <br />
+    #[cfg(feature = "tokio")]
+    fn open_async(&self, open_mode: &str) -> impl Future<Output = Result<tokio::fs::File>> + Send {
+        // The blocking task cannot borrow from self, copy what it needs
+        let url = if self.is_dir {
+            Err(SafError::IsADirectory)
+        } else {
+            Ok(self.url.clone())
+        };
+        let open_mode = open_mode.to_string();
+
+        async move {
+            let url = url?;
+            let file = tokio::task::spawn_blocking(move || open_content_url(&url, &open_mode))
+                .await
+                .map_err(|e| SafError::Other(format!("The blocking open task failed: {}", e)))??;
+            Ok(tokio::fs::File::from_std(file))
+        }
+    }
+
+    /// List the files in the directory represented by the AndroidFile object whose display name
+    /// contains "pattern", ignoring case, e.g. for a "search in this folder" box. The children
+    /// query carries a `_display_name LIKE ?` selection with the escaped pattern, which lets
+    /// providers that honor selections (e.g. MediaStore-backed ones) filter before rows are
+    /// transferred. SQL LIKE only folds ASCII case, so the selection is only sent for ASCII
+    /// patterns, which are then matched ignoring ASCII case; other patterns are matched ignoring
+    /// Unicode case and filtered here alone. Most document providers ignore selections, so the
+    /// names are always filtered here as well and the result is the same either way; only the
+    /// speed depends on the provider. <br />
+    /// RETURNS: The matching entries, sorted by name. <br />
+    fn list_with_name_like(&self, pattern: &str) -> Result<Vec<AndroidFile>> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!(
+            "Listing files named like {} in directory: {}",
+            pattern, self.url
+        );
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        if is_file_url(&self.url) {
+            let mut files = list_filesystem_children(env, self)?;
+            files.retain(|file| name_contains(&file.filename, pattern));
+            files.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+            return Ok(files);
+        }
+
+        let context = get_application_context()?;
+        let parent_uri = parse_uri(env, &self.url)?;
+        let tree_urls = TreeUrls::of(env, &parent_uri)?;
+
+        // Escape the LIKE wildcards, so the pattern is matched literally
+        let mut like = String::with_capacity(pattern.len() + 2);
+        like.push('%');
+        for c in pattern.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                like.push('\\');
+            }
+            like.push(c);
+        }
+        like.push('%');
+        let selection_args = [like.as_str()];
+        let selection = pattern
+            .is_ascii()
+            .then_some(("_display_name LIKE ? ESCAPE '\\'", &selection_args[..]));
+
+        let mut files = Vec::new();
+        for_each_child_row_where(
+            env,
+            &context,
+            &parent_uri,
+            selection,
+            None,
+            None,
+            |env, row| {
+                if !name_contains(&row.filename, pattern) {
+                    return Ok(());
+                }
+                let file = child_from_row(
+                    env,
+                    &context,
+                    self.document_file.as_obj(),
+                    &parent_uri,
+                    &tree_urls,
+                    &row,
+                )?;
+                files.push(file);
+                Ok(())
+            },
+        )?;
 
-                if !document_file.is_null() {
-                    let document_file_ref = env.new_global_ref(&document_file)?;
+        // Sort files by name
+        files.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+        Ok(files)
+    }
+
+    /// List the files in the directory represented by the AndroidFile object whose MIME type
+    /// starts with "mime_prefix", ignoring case, e.g. "image/" for a photo picker. Rows are
+    /// checked while the children cursor is read, so non-matching entries never become
+    /// AndroidFile objects. A trailing "*" is ignored ("image/*" is "image/"); directories match
+    /// only an explicit "vnd.android.document/directory" prefix, or "*/", which matches every
+    /// entry. <br />
+    /// RETURNS: The matching entries, sorted by name. <br />
+    fn list_files_filtered(&self, mime_prefix: &str) -> Result<Vec<AndroidFile>> {
+        // Check if the DocumentFile object represents a directory
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+        info!(
+            "Listing files of type {} in directory: {}",
+            mime_prefix, self.url
+        );
+
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let match_all = mime_prefix.starts_with("*/");
+        let prefix = mime_prefix.strip_suffix('*').unwrap_or(mime_prefix);
+
+        if is_file_url(&self.url) {
+            let mut files = list_filesystem_children(env, self)?;
+            files.retain(|file| match_all || mime_has_prefix(&file.mime_type, prefix));
+            files.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+            return Ok(files);
+        }
 
-                    files.push(AndroidFile {
-                        filename,
-                        size,
-                        path,
-                        url,
-                        is_dir,
-                        document_file: document_file_ref,
-                    });
-                }
+        let context = get_application_context()?;
+        let parent_uri = parse_uri(env, &self.url)?;
+        let tree_urls = TreeUrls::of(env, &parent_uri)?;
+        let mut files = Vec::new();
+        for_each_child_row(env, &context, &parent_uri, |env, row| {
+            if !match_all && !mime_has_prefix(&row.mime_type, prefix) {
+                return Ok(());
             }
-            // Close the cursor
-            env.call_method(&cursor, "close", "()V", &[])?.v()?;
-        }
+            let file = child_from_row(
+                env,
+                &context,
+                self.document_file.as_obj(),
+                &parent_uri,
+                &tree_urls,
+                &row,
+            )?;
+            files.push(file);
+            Ok(())
+        })?;
 
         // Sort files by name
-        files.sort_by(|a, b| a.filename.cmp(&b.filename));
+        files.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
 
         Ok(files)
     }
 
-    /// Create a new file in the directory represented by the AndroidFile object.
-    /// If self does not represent a directory, an error will be returned. <br />
-    /// PARAMS: MIME type and file name.
-    /// The MIME type should be a valid MIME type string, and the file name should not contain any
-    /// path separator. When MIME type and extension in file name mismatch, a correct extension will
-    /// be appended (thus it is recommended not to include extension).
-    /// When names collide, a number will be appended. <br />
-    /// RETURNS: A new AndroidFile object representing the newly created file. <br />
-    fn create_file(&self, mime_type: &str, file_name: &str) -> Result<AndroidFile> {
-        // Check if the DocumentFile object represents a directory
+    /// Read the whole file into memory and return it as a std::io::Cursor, for small files that
+    /// need a lot of random access (e.g. parsing a binary format with many seeks), where every
+    /// seek on a provider descriptor could be slow or unsupported. <br />
+    /// PARAMS: "max_size" is the largest size in bytes that is read. Larger files fail with a
+    /// TooLarge error before being read, and files that turn out to be larger than their reported
+    /// size fail once more than "max_size" bytes have arrived. <br />
+    fn open_cursor(&self, max_size: usize) -> Result<Cursor<Vec<u8>>> {
+        if self.size > max_size as u64 {
+            return Err(SafError::TooLarge {
+                size: self.size,
+                max: max_size as u64,
+            });
+        }
+
+        let mut content = Vec::with_capacity(self.size as usize);
+        // Read one byte more than allowed to detect files larger than reported
+        self.open("r")?
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut content)?;
+        if content.len() > max_size {
+            return Err(SafError::TooLarge {
+                size: content.len() as u64,
+                max: max_size as u64,
+            });
+        }
+
+        Ok(Cursor::new(content))
+    }
+
+    /// Mirror the directory represented by the AndroidFile object into the local directory
+    /// "local_dir", recursively, e.g. to keep an offline cache of a user-picked folder. Files are
+    /// copied when they are missing locally or differ in size or modification time; copies get
+    /// the modification time reported by the provider, so unchanged files are skipped on the next
+    /// sync. Files are written to a temporary name first and renamed when complete. With
+    /// `SyncOptions::delete_removed`, local entries that no longer exist in the source are
+    /// deleted. `SyncOptions::progress` is called before each copy and can cancel the sync,
+    /// leaving the files copied so far in place. <br />
+    /// RETURNS: The number of added, updated, deleted and unchanged entries. <br />
+    fn sync_into(&self, local_dir: &Path, mut options: SyncOptions) -> Result<SyncReport> {
         if !self.is_dir {
-            return Err(anyhow!("The provided URL does not point to a directory"));
+            return Err(SafError::NotADirectory);
+        }
+        info!("Syncing {} into {}", self.url, local_dir.display());
+
+        let mut report = SyncReport::default();
+        sync_directory(self, local_dir, "", &mut options, &mut report)?;
+        Ok(report)
+    }
+
+    /// Check the `is_dir` classification of the AndroidFile object against the live DocumentFile
+    /// (DocumentFile.isDirectory, which queries the provider for the MIME type) and return a
+    /// corrected copy if it is wrong, e.g. for objects built from a stale URL. Listing and
+    /// opening then work on the corrected object instead of failing on a misclassified one.
+    /// <br />
+    /// RETURNS: A copy of self with `is_dir` (and `size`, for files) refreshed, or an error if the
+    /// document no longer exists. <br />
+    fn verified(&self) -> Result<AndroidFile> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        let exists = env
+            .call_method(&self.document_file, "exists", "()Z", &[])?
+            .z()?;
+        if !exists {
+            return Err(SafError::NotFound(format!(
+                "The document {} does not exist",
+                self.url
+            )));
         }
+        let is_dir = env
+            .call_method(&self.document_file, "isDirectory", "()Z", &[])?
+            .z()?;
+        if is_dir == self.is_dir {
+            return Ok(self.clone());
+        }
+
         info!(
-            "Creating file named {} with MIME type {} in directory: {}",
-            file_name, mime_type, self.url
+            "Correcting {} from is_dir = {} to is_dir = {}",
+            self.url, self.is_dir, is_dir
         );
+        let size = if is_dir {
+            self.size
+        } else {
+            size_from_provider(
+                env.call_method(&self.document_file, "length", "()J", &[])?
+                    .j()?,
+            )
+        };
+        Ok(AndroidFile {
+            is_dir,
+            size,
+            ..self.clone()
+        })
+    }
+
+    /// Open the file for reading through a buffer and return a reader decoding little-endian
+    /// integers and floats (`read_u16`, `read_u32`, `read_f64`, ...), for parsing binary formats.
+    #[cfg(feature = "binary")]
+    fn open_le_reader(&self) -> Result<LeReader> {
+        Ok(LeReader::new(self.open("r")?))
+    }
 
+    /// Like `open_le_reader`, decoding big-endian (network byte order) values.
+    #[cfg(feature = "binary")]
+    fn open_be_reader(&self) -> Result<BeReader> {
+        Ok(BeReader::new(self.open("r")?))
+    }
+
+    /// Read any column of the document by name, e.g. provider-specific metadata such as sharing
+    /// status or owner that the fixed listing projection does not include. The document is
+    /// queried with a projection of just that column and the value is read as a string (numbers
+    /// are converted by the cursor). <br />
+    /// RETURNS: The value, or None if it is NULL or the provider does not know the column;
+    /// unknown columns are not an error. <br />
+    fn query_column(&self, column: &str) -> Result<Option<String>> {
         // Obtain JNIEnv using improved get_env function
         let mut env_guard = get_env()?;
         let env = &mut *env_guard;
+        let context = get_application_context()?;
 
-        // Convert MIME type and file name to Java strings
-        let mime_type_str = env.new_string(mime_type)?;
-        let file_name_str = env.new_string(file_name)?;
+        let uri = parse_uri(env, &self.url)?;
+        query_document_string(env, &context, &uri, column)
+    }
 
-        // Create a new file in the directory
-        let new_file = env.call_method(
-            &self.document_file,
-            "createFile",
-            "(Ljava/lang/String;Ljava/lang/String;)Landroidx/documentfile/provider/DocumentFile;",
-            &[JValueGen::Object(&mime_type_str), JValueGen::Object(&file_name_str)],
-        )?.l()?;
+    /// Open the file for writing ("w" mode) and return a writer that collects small writes and
+    /// passes them to the provider once "max_buffer" bytes are buffered or "flush_interval" has
+    /// passed since the last flush, whichever comes first. This saves a provider round-trip per
+    /// write for workloads like appending log lines, while bounding how stale the document gets.
+    /// The interval is checked on each write, so nothing is flushed while the writer is idle;
+    /// call `flush` for that. Call `CoalescedWriter::finish` to write the rest and observe
+    /// errors.
+    fn open_coalesced_writer(
+        &self,
+        flush_interval: Duration,
+        max_buffer: usize,
+    ) -> Result<CoalescedWriter> {
+        Ok(CoalescedWriter::new(
+            self.open("w")?,
+            flush_interval,
+            max_buffer,
+        ))
+    }
+
+    /// Get the authority of the provider serving the AndroidFile object, e.g.
+    /// "com.android.externalstorage.documents".
+    fn authority(&self) -> Result<String> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
 
-        Ok(from_document_file(&new_file)?)
+        let uri = parse_uri(env, &self.url)?;
+        let authority = env
+            .call_method(&uri, "getAuthority", "()Ljava/lang/String;", &[])?
+            .l()?;
+        if authority.is_null() {
+            return Err(SafError::Other(format!(
+                "The URL {} has no authority",
+                self.url
+            )));
+        }
+        jstring_to_string(env, authority)
     }
 
-    /// Create a new directory in the directory represented by the AndroidFile object.
-    /// If self does not represent a directory, an error will be returned. <br />
-    /// PARAMS: Directory name. When names collide, the file name will be appended with a number. <br />
-    /// RETURNS: A new AndroidFile object representing the newly created directory. <br />
-    fn create_directory(&self, dir_name: &str) -> Result<AndroidFile> {
-        // Check if the DocumentFile object represents a directory
+    /// Get the document ID of the root of the tree the AndroidFile object belongs to, e.g.
+    /// "primary:Music". Together with `authority`, it can be passed to `from_tree_parts` to get
+    /// the tree back. <br />
+    /// RETURNS: The tree document ID, or None if the object is not part of a tree. <br />
+    fn tree_document_id(&self) -> Result<Option<String>> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        let uri = parse_uri(env, &self.url)?;
+        let documents_contract_class = "android/provider/DocumentsContract";
+        let is_tree_uri = env
+            .call_static_method(
+                documents_contract_class,
+                "isTreeUri",
+                "(Landroid/net/Uri;)Z",
+                &[JValueGen::Object(&uri)],
+            )?
+            .z()?;
+        if !is_tree_uri {
+            return Ok(None);
+        }
+        let tree_document_id = env
+            .call_static_method(
+                documents_contract_class,
+                "getTreeDocumentId",
+                "(Landroid/net/Uri;)Ljava/lang/String;",
+                &[JValueGen::Object(&uri)],
+            )?
+            .l()?;
+        Ok(Some(jstring_to_string(env, tree_document_id)?))
+    }
+
+    /// Estimate a copy of the AndroidFile object (a file or a whole directory tree) into
+    /// "dest_dir" before starting it, so a UI can show an ETA instead of a spinner. The source is
+    /// walked to sum up sizes and count files, then up to 256 KiB of the largest file are read
+    /// and written into a temporary file in "dest_dir", which is deleted afterwards, to measure
+    /// the throughput between the two providers. Small samples overestimate the cost of latency,
+    /// so treat the result as a rough figure. <br />
+    /// RETURNS: The total bytes and file count, and the probed throughput in bytes per second, or
+    /// None for the throughput if the probe failed (e.g. "dest_dir" is read-only). <br />
+    fn estimate_copy(&self, dest_dir: &AndroidFile) -> Result<CopyEstimate> {
+        if !dest_dir.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        let mut estimate = CopyEstimate {
+            total_bytes: 0,
+            file_count: 0,
+            probed_throughput: None,
+        };
+        let mut largest: Option<AndroidFile> = None;
+        let mut pending = vec![self.clone()];
+        while let Some(entry) = pending.pop() {
+            if entry.is_dir {
+                pending.extend(entry.list_files()?);
+                continue;
+            }
+            estimate.total_bytes += entry.size;
+            estimate.file_count += 1;
+            if largest
+                .as_ref()
+                .is_none_or(|largest| entry.size > largest.size)
+            {
+                largest = Some(entry);
+            }
+        }
+
+        if let Some(sample) = largest.filter(|sample| sample.size > 0) {
+            let probe = (|| -> Result<u64> {
+                let start = Instant::now();
+                let mut buffer = Vec::with_capacity(COPY_PROBE_LEN);
+                sample
+                    .open("r")?
+                    .take(COPY_PROBE_LEN as u64)
+                    .read_to_end(&mut buffer)?;
+
+                let probe_file = dest_dir.create_file(MIME_TYPE_BINARY, ".copy-probe")?;
+                let written = probe_file.open("w").and_then(|mut file| {
+                    file.write_all(&buffer)?;
+                    Ok(file.sync_all()?)
+                });
+                let elapsed = start.elapsed();
+                let _ = probe_file.remove_file();
+                written?;
+
+                Ok((buffer.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64)
+            })();
+            estimate.probed_throughput = probe
+                .inspect_err(|e| info!("Copy throughput probe failed: {}", e))
+                .ok();
+        }
+
+        Ok(estimate)
+    }
+
+    /// Open the file for a long streaming read that survives the provider invalidating the file
+    /// descriptor, as cloud providers may do when they re-sync a document. On such an error the
+    /// document is reopened and the reader seeks back to the last successful offset, at most three
+    /// times. Resuming needs a seekable descriptor; if the provider serves the document through a
+    /// pipe, the read error is returned as usual. <br />
+    /// RETURNS: A ResilientReader positioned at the start of the document. <br />
+    fn open_resilient_read(&self) -> Result<ResilientReader> {
+        let file = self.open("r")?;
+        Ok(ResilientReader::new(file, self.url.clone()))
+    }
+
+    /// Truncate (or extend with zeros) the file to "len" bytes in place, without rewriting it.
+    /// The document is opened in "rw" mode, since plain "w" may already truncate it to zero on
+    /// some providers, and the length is set on the descriptor. This only works when the provider
+    /// hands out a seekable descriptor to a real file. <br />
+    /// PARAMS: "len" - The new length of the file in bytes. <br />
+    /// RETURNS: A ModeUnsupported error if the provider rejects "rw" mode, a NotSeekable error if
+    /// the descriptor cannot be truncated (e.g. it is a pipe). <br />
+    fn truncate(&self, len: u64) -> Result<()> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        // Providers reject modes they do not support with IllegalArgumentException or
+        // UnsupportedOperationException, other failures are passed on unchanged
+        let file = self.open("rw").map_err(|e| match e {
+            SafError::ProviderException(message)
+                if message.starts_with("java.lang.IllegalArgumentException") =>
+            {
+                SafError::ModeUnsupported(format!(
+                    "The provider does not support opening {} in \"rw\" mode: {}",
+                    self.url, message
+                ))
+            }
+            SafError::Unsupported(message) => SafError::ModeUnsupported(format!(
+                "The provider does not support opening {} in \"rw\" mode: {}",
+                self.url, message
+            )),
+            e => e,
+        })?;
+        if let Err(e) = file.set_len(len) {
+            return Err(match e.raw_os_error() {
+                Some(libc::EINVAL | libc::ESPIPE) => SafError::NotSeekable(format!(
+                    "The descriptor of {} is not seekable: {}",
+                    self.url, e
+                )),
+                _ => e.into(),
+            });
+        }
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Call "f" for every entry of the directory represented by the AndroidFile object, in the
+    /// order the provider returns them. Entries are read from the children cursor one at a time,
+    /// so memory use does not grow with the size of the directory. Iteration stops at the first
+    /// error returned by "f", and the cursor is closed in any case. Note that "f" runs while the
+    /// cursor is open and the thread is attached to the JVM, so it should not block for long. <br />
+    /// PARAMS: "f" - The callback, receiving each entry with full metadata. <br />
+    /// RETURNS: The first error returned by "f" or by the query. <br />
+    fn for_each_child<F: FnMut(AndroidFile) -> Result<()>>(&self, mut f: F) -> Result<()> {
         if !self.is_dir {
-            return Err(anyhow!("The provided URL does not point to a directory"));
+            return Err(SafError::NotADirectory);
         }
-        info!(
-            "Creating directory named {} in directory: {}",
-            dir_name, self.url
-        );
 
         // Obtain JNIEnv using improved get_env function
         let mut env_guard = get_env()?;
         let env = &mut *env_guard;
+        let context = get_application_context()?;
 
-        // Convert directory name to Java string
-        let file_name_str = env.new_string(dir_name)?;
+        let parent_uri = parse_uri(env, &self.url)?;
 
-        // Create a new file in the directory
-        let new_dir = env
-            .call_method(
-                &self.document_file,
-                "createDirectory",
-                "(Ljava/lang/String;)Landroidx/documentfile/provider/DocumentFile;",
-                &[JValueGen::Object(&file_name_str)],
-            )?
+        // Filesystem directories have no children cursor, see list_children
+        let scheme = env
+            .call_method(&parent_uri, "getScheme", "()Ljava/lang/String;", &[])?
             .l()?;
+        if !scheme.is_null() && jstring_to_string(env, scheme)? == "file" {
+            drop(env_guard);
+            return self.list_files()?.into_iter().try_for_each(f);
+        }
 
-        Ok(from_document_file(&new_dir)?)
+        let tree_urls = TreeUrls::of(env, &parent_uri)?;
+        for_each_child_row(env, &context, &parent_uri, |env, row| {
+            f(child_from_row(
+                env,
+                &context,
+                self.document_file.as_obj(),
+                &parent_uri,
+                &tree_urls,
+                &row,
+            )?)
+        })
     }
 
-    /// Remove the file or directory represented by the AndroidFile object. If the object represents
-    /// a directory, the directory will be removed recursively. The method will return true if the
-    /// file or directory is removed successfully, or false if the file or directory does not exist.
-    fn remove_file(&self) -> Result<bool> {
+    /// Iterate over the entries of the directory represented by the AndroidFile object, in the
+    /// order the provider returns them. Unlike `list_files`, each entry is read from the children
+    /// cursor only when the iterator advances, so stopping early (e.g. at the first match) skips
+    /// the remaining rows. The cursor is closed when the iterator is exhausted or dropped. <br />
+    /// RETURNS: An iterator yielding each entry with full metadata; an error ends the iteration.
+    /// <br />
+    fn iter_files(&self) -> Result<DocumentIter> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
         // Obtain JNIEnv using improved get_env function
         let mut env_guard = get_env()?;
         let env = &mut *env_guard;
+        let context = get_application_context()?;
 
-        // Delete the file or directory
-        let result = env
-            .call_method(self.document_file.as_obj(), "delete", "()Z", &[])?
-            .z()?;
+        env.with_local_frame(16, |env| -> Result<DocumentIter> {
+            let parent_uri = parse_uri(env, &self.url)?;
 
-        Ok(result)
+            // Filesystem directories have no children cursor, see list_children
+            let scheme = env
+                .call_method(&parent_uri, "getScheme", "()Ljava/lang/String;", &[])?
+                .l()?;
+            let (cursor, tree_urls, listed) =
+                if !scheme.is_null() && jstring_to_string(env, scheme)? == "file" {
+                    let listed = list_children(env, &context, self, SortOrder::NameAsc, None)?;
+                    // Only used for cursor rows
+                    (None, TreeUrls::default(), listed)
+                } else {
+                    let tree_urls = TreeUrls::of(env, &parent_uri)?;
+                    let cursor = query_children(env, &context, &parent_uri, None, None, None)?;
+                    (Some(env.new_global_ref(cursor)?), tree_urls, Vec::new())
+                };
+
+            Ok(DocumentIter {
+                context: context.clone(),
+                parent: self.document_file.clone(),
+                parent_uri: env.new_global_ref(parent_uri)?,
+                tree_urls,
+                cursor,
+                listed: listed.into_iter(),
+            })
+        })
+    }
+
+    /// Predict the display name `create_file` would give a new file in the directory represented
+    /// by the AndroidFile object, without creating anything. This follows the rules of the
+    /// platform's providers (FileUtils.buildUniqueFile): characters invalid on FAT filesystems are
+    /// replaced by underscores, the extension matching "mime_type" is appended unless the name
+    /// already has an extension of that type, and " (1)", " (2)", ... is inserted before the
+    /// extension while the name is taken. The final name is chosen by the provider, so this is a
+    /// best-effort prediction; providers with other rules (e.g. cloud providers allowing
+    /// duplicate names) may differ. <br />
+    /// RETURNS: The predicted display name, or an error if every numbered name is taken. <br />
+    fn preview_created_name(&self, mime_type: &str, file_name: &str) -> Result<String> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        let sanitized = sanitize_display_name(file_name);
+
+        // Split into base name and extension the way the provider will
+        let (base, extension) = if mime_type == MIME_TYPE_DIR {
+            (sanitized, None)
+        } else {
+            // Obtain JNIEnv using improved get_env function
+            let mut env_guard = get_env()?;
+            let env = &mut *env_guard;
+
+            let current_extension = name_extension(&sanitized).map(str::to_owned);
+            let current_mime_type = match &current_extension {
+                Some(extension) => mime_type_map_lookup(
+                    env,
+                    "getMimeTypeFromExtension",
+                    &extension.to_lowercase(),
+                )?,
+                None => None,
+            };
+            match current_extension {
+                Some(extension) if current_mime_type.as_deref() == Some(mime_type) => {
+                    let stem_len = sanitized.len() - extension.len() - 1;
+                    (sanitized[..stem_len].to_owned(), Some(extension))
+                }
+                _ => (
+                    sanitized,
+                    mime_type_map_lookup(env, "getExtensionFromMimeType", mime_type)?,
+                ),
+            }
+        };
+
+        let mut candidate = numbered_name(&base, extension.as_deref(), 0);
+        let mut counter = 0;
+        while child_document(self, &candidate)?.is_some() {
+            counter += 1;
+            if counter > 32 {
+                return Err(SafError::AlreadyExists(format!(
+                    "Failed to find a unique name for {}",
+                    file_name
+                )));
+            }
+            candidate = numbered_name(&base, extension.as_deref(), counter);
+        }
+
+        Ok(candidate)
+    }
+
+    /// Rename the file or directory represented by the AndroidFile object in place, keeping its
+    /// content, with DocumentsContract.renameDocument. Most providers derive the document ID from
+    /// the name, so the URI usually changes and the AndroidFile object must be replaced by the
+    /// returned one; providers that keep the URI are handled as well. <br />
+    /// PARAMS: "new_name" - The new display name, including any extension. <br />
+    /// RETURNS: The renamed AndroidFile object, or an error if an entry with the new name already
+    /// exists in the parent directory or the provider does not support renaming. <br />
+    fn rename(&self, new_name: &str) -> Result<AndroidFile> {
+        if new_name == self.filename {
+            return Ok(self.clone());
+        }
+
+        // Providers either fail or pick another name on a collision, report it clearly instead.
+        // Best effort: without a resolvable parent, leave the check to the provider
+        if let Some(parent) = parent_document(self).ok().flatten() {
+            if child_document(&parent, new_name)?.is_some() {
+                return Err(SafError::AlreadyExists(format!(
+                    "An entry named {} already exists in {}",
+                    new_name, parent.url
+                )));
+            }
+        }
+
+        rename_document(self, new_name)
+    }
+
+    /// Copy the file represented by the AndroidFile object into "dest_dir", which may belong to
+    /// another tree or provider. The new file is created with the MIME type the provider reports
+    /// for the source, and the content is streamed between the two file descriptors in chunks
+    /// (or copied by the kernel when both are local files), so large files are never held in
+    /// memory. If the copy fails, the partially written file is removed. Directories cannot be
+    /// copied with this method. <br />
+    /// PARAMS: "new_name" - The name of the copy, or None to keep the source's name. <br />
+    /// RETURNS: A new AndroidFile object representing the copy. <br />
+    fn copy_to(&self, dest_dir: &AndroidFile, new_name: Option<&str>) -> Result<AndroidFile> {
+        copy_document(self, dest_dir, new_name, None)
+    }
+
+    /// Copy the file into "dest_dir" like `copy_to`, checking "cancellation" before every chunk.
+    /// A cancelled copy removes the partially written file like a failed one, and returns a
+    /// Cancelled error. <br />
+    /// RETURNS: A new AndroidFile object representing the copy. <br />
+    fn copy_to_with_cancellation(
+        &self,
+        dest_dir: &AndroidFile,
+        new_name: Option<&str>,
+        cancellation: &CancellationToken,
+    ) -> Result<AndroidFile> {
+        copy_document(self, dest_dir, new_name, Some(cancellation))
+    }
+
+    /// Move the file represented by the AndroidFile object into "dest_dir". When both are in the
+    /// same tree and the provider advertises FLAG_SUPPORTS_MOVE for the document, the provider
+    /// moves it natively (directories included); otherwise the file is copied with `copy_to` and
+    /// the source is removed afterwards, which is not supported for directories. If the parent of
+    /// the source cannot be resolved, the error is returned instead of falling back. <br />
+    /// RETURNS: The AndroidFile object at the new location, whose URI differs from the source's.
+    /// If the copy succeeds but the source cannot be removed, an error is returned and both
+    /// files remain. <br />
+    fn move_to(&self, dest_dir: &AndroidFile) -> Result<AndroidFile> {
+        if !dest_dir.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        if let Some(moved) = move_document(self, dest_dir)? {
+            return Ok(moved);
+        }
+
+        let copy = self.copy_to(dest_dir, None)?;
+        if !self.remove_file()? {
+            return Err(SafError::Other(format!(
+                "Copied {} to {}, but failed to remove the source",
+                self.url, copy.url
+            )));
+        }
+        Ok(copy)
+    }
+
+    /// Walk the whole tree below the directory represented by the AndroidFile object, depth-first,
+    /// returning every file and subdirectory. A directory is followed by its own descendants
+    /// before its next sibling, and siblings are sorted by name, so the result can be rendered as
+    /// a tree directly. The thread is attached to the JVM once for the whole walk. Directories
+    /// that were already visited (a provider exposing a cycle) are listed but not descended
+    /// into again. <br />
+    /// RETURNS: The entries with their depth and their path relative to this directory. <br />
+    fn walk(&self) -> Result<Vec<WalkEntry>> {
+        walk_tree(self, None)
+    }
+
+    /// Walk the tree like `walk`, aborting with a Cancelled error once "cancellation" is
+    /// cancelled. The running children query is aborted through its CancellationSignal, and the
+    /// token is checked before every row and every directory. <br />
+    fn walk_with_cancellation(&self, cancellation: &CancellationToken) -> Result<Vec<WalkEntry>> {
+        walk_tree(self, Some(cancellation))
+    }
+
+    /// Check whether the document still exists, e.g. for a URL stored across app restarts. The
+    /// DocumentFile reference held by the AndroidFile object stays valid after the document is
+    /// deleted, so this asks the provider with a live query (DocumentFile.exists) every time; it
+    /// is not free and should not be called in a tight loop. <br />
+    /// RETURNS: false if the document is gone or cannot be queried any more (e.g. the permission
+    /// was revoked). <br />
+    fn exists(&self) -> Result<bool> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+
+        Ok(env
+            .call_method(&self.document_file, "exists", "()Z", &[])?
+            .z()?)
+    }
+
+    /// Look up a direct child of the directory represented by the AndroidFile object by its
+    /// display name (DocumentFile.findFile), without building AndroidFile objects for the other
+    /// entries. The provider still scans the directory, but this is much cheaper than
+    /// `list_files` for large folders, and the natural first step of "open or create". <br />
+    /// RETURNS: The child, or None if there is no entry with that name. <br />
+    fn find_file(&self, name: &str) -> Result<Option<AndroidFile>> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        child_document(self, name)
+    }
+
+    /// Create a directory and any missing intermediate directories below the directory
+    /// represented by the AndroidFile object, like `mkdir -p`. Existing directories along the way
+    /// are reused; leading, trailing and repeated slashes are ignored. <br />
+    /// PARAMS: "relative_path" - Slash-separated path, e.g. "a/b/c". <br />
+    /// RETURNS: The deepest directory, or self if the path has no components. An error is
+    /// returned if a component exists as a file. <br />
+    fn create_directories(&self, relative_path: &str) -> Result<AndroidFile> {
+        if !self.is_dir {
+            return Err(SafError::NotADirectory);
+        }
+
+        let mut current = self.clone();
+        for name in relative_path.split('/').filter(|name| !name.is_empty()) {
+            current = match current.find_file(name)? {
+                Some(existing) if existing.is_dir => existing,
+                Some(existing) => {
+                    return Err(SafError::AlreadyExists(format!(
+                        "Cannot create directory {}: a file with that name exists at {}",
+                        name, existing.url
+                    )));
+                }
+                None => current.create_directory(name)?,
+            };
+        }
+
+        Ok(current)
+    }
+
+    /// Read the whole content of the file into memory, for small files like configuration.
+    /// The descriptor is closed before returning. <br />
+    /// RETURNS: The content of the file. <br />
+    fn read_to_bytes(&self) -> Result<Vec<u8>> {
+        let mut file = self.open("r")?;
+        // The size is only a hint, it may not fit into usize on 32-bit targets
+        let mut data = Vec::with_capacity(usize::try_from(self.size).unwrap_or_default());
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Read the file line by line, e.g. to parse a log or CSV file, through a 64 KiB read
+    /// buffer like `open_buffered`. The content is assumed to be UTF-8; use `detect_encoding`
+    /// first if it may not be. Lines are split at "\n" and a trailing "\r" is removed. <br />
+    /// RETURNS: An iterator over the lines, without line endings. A read error or a line that is
+    /// not valid UTF-8 is yielded as an error item. <br />
+    fn lines(&self) -> Result<impl Iterator<Item = Result<String>>> {
+        Ok(self
+            .open_buffered("r")?
+            .lines()
+            .map(|line| line.map_err(SafError::from)))
+    }
+
+    /// Replace the content of the file with "data". The file is opened in "wt" mode, so any
+    /// previous content is truncated rather than partially overwritten, and the descriptor is
+    /// closed before returning. <br />
+    fn write_bytes(&self, data: &[u8]) -> Result<()> {
+        let mut file = self.open("wt")?;
+        file.write_all(data)?;
+        Ok(())
+    }
+
+    /// Open the file in "wa" mode for appending. Some providers ignore the append flag and hand
+    /// out a descriptor positioned at the start, so a seekable descriptor is moved to the end of
+    /// the file before it is returned. Note that this cannot undo a provider that truncates the
+    /// file on "wa"; use `append_bytes` when the existing content must survive. <br />
+    fn open_append(&self) -> Result<File> {
+        let mut file = self.open("wa")?;
+        if file.metadata()?.file_type().is_file() {
+            file.seek(SeekFrom::End(0))?;
+        }
+        Ok(file)
+    }
+
+    /// Append "data" to the end of the file without losing its content on providers that do
+    /// not honor "wa". The file is opened in "rw" mode, which never truncates, and written at
+    /// the end when the descriptor is seekable. Otherwise the content is read first and the file
+    /// is opened in "wa"; if the descriptor does not keep the old length (it was truncated, or
+    /// it is a pipe), the file is rewritten with the old content followed by "data". <br />
+    /// PARAMS: "data" - The bytes to append. <br />
+    fn append_bytes(&self, data: &[u8]) -> Result<()> {
+        if self.is_dir {
+            return Err(SafError::IsADirectory);
+        }
+
+        if let Ok(mut file) = self.open("rw") {
+            if file.metadata()?.file_type().is_file() {
+                file.seek(SeekFrom::End(0))?;
+                file.write_all(data)?;
+                return Ok(());
+            }
+        }
+
+        // Keep a copy of the content, "wa" may truncate the file as soon as it is opened
+        let mut content = self.read_to_bytes()?;
+        let mut file = self.open("wa")?;
+        let metadata = file.metadata()?;
+        if metadata.file_type().is_file() && metadata.len() == content.len() as u64 {
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(data)?;
+            return Ok(());
+        }
+        drop(file);
+
+        content.extend_from_slice(data);
+        self.write_bytes(&content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn external_storage_tree() -> TreeUrls {
+        TreeUrls {
+            path: "/tree/primary:Music".to_string(),
+            url: "content://com.android.externalstorage.documents/tree/primary%3AMusic".to_string(),
+        }
+    }
+
+    // Expected values are what DocumentsContract.buildDocumentUriUsingTree returns for the
+    // same tree and document ID
+    #[test]
+    fn tree_urls_child_matches_build_document_uri_using_tree() {
+        let (path, url) = external_storage_tree().child("primary:Music/Album/song.mp3");
+        assert_eq!(
+            path,
+            "/tree/primary:Music/document/primary:Music/Album/song.mp3"
+        );
+        assert_eq!(
+            url,
+            "content://com.android.externalstorage.documents/tree/primary%3AMusic/document/primary%3AMusic%2FAlbum%2Fsong.mp3"
+        );
+    }
+
+    #[test]
+    fn tree_urls_child_encodes_spaces_percent_and_non_ascii() {
+        let (path, url) = external_storage_tree().child("primary:Music/50% off ç.mp3");
+        assert_eq!(
+            path,
+            "/tree/primary:Music/document/primary:Music/50% off ç.mp3"
+        );
+        assert!(url.ends_with("/document/primary%3AMusic%2F50%25%20off%20%C3%A7.mp3"));
+    }
+
+    #[test]
+    fn tree_urls_child_keeps_opaque_ids() {
+        let (_, url) = external_storage_tree().child("msf:1234");
+        assert!(url.ends_with("/document/msf%3A1234"));
+    }
+
+    fn entry<'a>(filename: &'a str, document_id: &'a str) -> SortFields<'a> {
+        SortFields {
+            filename,
+            document_id,
+            last_modified: Some(1_000),
+            size: Some(10),
+        }
+    }
+
+    #[test]
+    fn same_named_entries_are_ordered_by_document_id() {
+        let entries = [
+            entry("b.txt", "doc:3"),
+            entry("a.txt", "doc:9"),
+            entry("a.txt", "doc:2"),
+            entry("a.txt", "doc:5"),
+        ];
+        let expected = [
+            entry("a.txt", "doc:2"),
+            entry("a.txt", "doc:5"),
+            entry("a.txt", "doc:9"),
+            entry("b.txt", "doc:3"),
+        ];
+
+        // Every input order, as different providers or repeated queries may return, sorts the
+        // same way for each order that falls back to the name
+        let mut shuffled = entries.to_vec();
+        for _ in 0..entries.len() {
+            shuffled.rotate_left(1);
+            for order in [
+                SortOrder::NameAsc,
+                SortOrder::ModifiedDesc,
+                SortOrder::SizeDesc,
+            ] {
+                let mut sorted = shuffled.clone();
+                sorted.sort_by(|a, b| order.compare_fields(a, b));
+                assert_eq!(sorted, expected, "{:?}", order);
+            }
+            let mut sorted = shuffled.clone();
+            sorted.sort_by(|a, b| SortOrder::NameDesc.compare_fields(a, b));
+            assert!(sorted.iter().rev().eq(expected.iter()));
+        }
+    }
+
+    #[cfg(feature = "hashing")]
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // Test vectors from FIPS 180-2
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn sha256_matches_known_vectors() {
+        assert_eq!(
+            hex(&sha256_of(&b""[..]).unwrap()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256_of(&b"abc"[..]).unwrap()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Spans several chunks
+        let million_a = vec![b'a'; 1_000_000];
+        assert_eq!(
+            hex(&sha256_of(&million_a[..]).unwrap()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn crc32_matches_check_value() {
+        assert_eq!(crc32_of(&b"123456789"[..]).unwrap(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn sizes_above_u32_max_are_kept() {
+        let five_gib = 5 * 1024 * 1024 * 1024_i64;
+        assert_eq!(size_from_provider(five_gib), 5_368_709_120);
+        assert_eq!(size_from_provider(u32::MAX as i64 + 1), u32::MAX as u64 + 1);
+        assert_eq!(size_from_provider(i64::MAX), i64::MAX as u64);
+    }
+
+    #[test]
+    fn negative_sizes_are_zero() {
+        assert_eq!(size_from_provider(-1), 0);
+        assert_eq!(size_from_provider(i64::MIN), 0);
+    }
+
+    #[test]
+    fn null_cursor_is_query_failed_with_permission() {
+        let error = null_cursor_error("content://a/tree/b", PermissionLevel::Read);
+        assert!(matches!(error, SafError::QueryFailed(_)));
+    }
+
+    #[test]
+    fn null_cursor_without_permission_is_permission_denied() {
+        let error = null_cursor_error("content://a/tree/b", PermissionLevel::None);
+        assert!(matches!(error, SafError::PermissionDenied(_)));
+    }
+
+    #[test]
+    fn name_extension_takes_the_last_dot() {
+        assert_eq!(name_extension("notes.txt"), Some("txt"));
+        assert_eq!(name_extension("archive.tar.gz"), Some("gz"));
+        assert_eq!(name_extension("a.b.c.d"), Some("d"));
+        assert_eq!(name_extension(".config.json"), Some("json"));
+    }
+
+    #[test]
+    fn names_without_extension() {
+        assert_eq!(name_extension("README"), None);
+        assert_eq!(name_extension(""), None);
+        assert_eq!(name_extension("trailing."), None);
+        assert_eq!(name_extension("many.dots.."), None);
+        // Dotfiles are hidden files, not extensions
+        assert_eq!(name_extension(".nomedia"), None);
+        assert_eq!(name_extension("."), None);
+    }
+
+    #[test]
+    fn sanitize_display_name_replaces_invalid_characters() {
+        assert_eq!(sanitize_display_name("a/b\\c:d"), "a_b_c_d");
+        assert_eq!(sanitize_display_name("what?*<>|\""), "what______");
+        assert_eq!(sanitize_display_name("tab\there\x7f"), "tab_here_");
+        assert_eq!(sanitize_display_name(".nomedia"), ".nomedia");
+        assert_eq!(sanitize_display_name("a..b. 音楽"), "a..b. 音楽");
+    }
+
+    #[test]
+    fn numbered_name_goes_before_the_extension() {
+        assert_eq!(numbered_name("notes", Some("txt"), 0), "notes.txt");
+        assert_eq!(numbered_name("notes", Some("txt"), 2), "notes (2).txt");
+        assert_eq!(
+            numbered_name("archive.tar", Some("gz"), 1),
+            "archive.tar (1).gz"
+        );
+        assert_eq!(numbered_name("README", None, 0), "README");
+        assert_eq!(numbered_name("README", None, 3), "README (3)");
+        assert_eq!(numbered_name(".nomedia", None, 1), ".nomedia (1)");
+    }
+
+    #[test]
+    fn name_contains_ascii_patterns_like_sql() {
+        assert!(name_contains("Holiday Photo.JPG", "photo"));
+        assert!(name_contains("100%_done.txt", "%_D"));
+        assert!(!name_contains("notes.txt", "photo"));
+        // SQL LIKE does not fold non-ASCII letters, and neither does an ASCII pattern
+        assert!(!name_contains("\u{212A}elvin.txt", "kelvin"));
+    }
+
+    #[test]
+    fn name_contains_non_ascii_patterns_ignore_unicode_case() {
+        assert!(name_contains("Été 2024.jpg", "été"));
+        assert!(name_contains("été 2024.jpg", "ÉTÉ"));
+        assert!(name_contains("Ärger.txt", "ärg"));
+        assert!(!name_contains("Ete 2024.jpg", "été"));
     }
 }
@@ -0,0 +1,219 @@
+use std::{
+    ffi::c_void,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use jni::{
+    objects::{GlobalRef, JObject, JValueGen},
+    sys::jlong,
+    JNIEnv, NativeMethod,
+};
+use log::{error, info};
+
+use crate::error::{Result, SafError};
+use crate::jni_utils::{check_exception, find_class, get_application_context, get_env};
+use crate::ndk_saf::{content_resolver, jstring_to_string};
+
+// Simple name of the ContentObserver subclass the app ships next to its MainActivity, see
+// `watch_uri`
+const OBSERVER_CLASS: &str = "SafContentObserver";
+
+// Callback of a ContentObserver watch; the Mutex makes it safe to call from any binder thread
+type WatchCallback = Mutex<Box<dyn Fn() + Send>>;
+
+// Keeps a watch running; dropping it (or calling `stop`) ends the watch
+#[derive(Debug)]
+pub struct ObserverHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ObserverHandle {
+    /// Run "poll" on a background thread every "interval" until the handle is dropped. The
+    /// thread ends early when "poll" returns false.
+    pub(crate) fn polling<F>(interval: Duration, mut poll: F) -> Self
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        // Anything but a timeout means a stop was requested or the handle is gone
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if !poll() {
+                    break;
+                }
+            }
+        });
+
+        ObserverHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop the watch and wait for a callback that is currently running to return.
+    pub fn stop(self) {
+        // Dropping does the work
+    }
+}
+
+impl Drop for ObserverHandle {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            // The callback may drop the handle itself, do not wait for the own thread
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+// Keeps a ContentObserver registered; dropping it (or calling `stop`) unregisters it
+#[derive(Debug)]
+pub struct WatchHandle {
+    observer: GlobalRef, // The SafContentObserver Java object
+    callback: jlong,     // Arc<WatchCallback> turned into a raw pointer, owned by the handle
+}
+
+impl WatchHandle {
+    /// Unregister the observer and wait for a callback that is currently running to return.
+    pub fn stop(self) {
+        // Dropping does the work
+    }
+
+    /// Unregister the observer, then close it so it stops calling into native code.
+    fn unregister(&self) -> Result<()> {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+
+        env.with_local_frame(8, |env| -> Result<()> {
+            let content_resolver = content_resolver(env, &context)?;
+            env.call_method(
+                &content_resolver,
+                "unregisterContentObserver",
+                "(Landroid/database/ContentObserver;)V",
+                &[JValueGen::Object(self.observer.as_obj())],
+            )?;
+            // Synchronized with onChange, so no notification is delivered after this returns
+            env.call_method(self.observer.as_obj(), "close", "()V", &[])?;
+            Ok(())
+        })
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        match self.unregister() {
+            // SAFETY: the pointer comes from Arc::into_raw in watch_uri and is released only here
+            Ok(()) => unsafe { drop(Arc::from_raw(self.callback as *const WatchCallback)) },
+            // The observer may still call into native code, keep the callback alive
+            Err(e) => error!(
+                "Failed to unregister the ContentObserver, leaking it: {}",
+                e
+            ),
+        }
+    }
+}
+
+/// Register a ContentObserver on "uri" and its descendants that calls "callback" whenever the
+/// provider reports a change. Android has no ContentObserver that calls into native code, so
+/// the app must ship a small subclass named SafContentObserver in its package (next to its
+/// MainActivity), as the demo app does; its onChange is bound to a native method here. No
+/// Handler is passed, so "callback" runs on a binder thread of the app, one call at a time.
+/// Providers may coalesce notifications or send none at all. <br />
+/// RETURNS: A handle that keeps the observer registered until it is dropped, or an error of
+/// kind Unsupported if the app does not ship the observer class. <br />
+pub(crate) fn watch_uri(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    uri: &JObject,
+    callback: Box<dyn Fn() + Send>,
+) -> Result<WatchHandle> {
+    let package_name = env
+        .call_method(context, "getPackageName", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let package_name = jstring_to_string(env, package_name)?;
+    let class_name = format!("{}/{}", package_name.replace('.', "/"), OBSERVER_CLASS);
+    let class = find_class(&class_name);
+    if class.is_err() {
+        env.exception_clear()?;
+        return Err(SafError::Unsupported(format!(
+            "The app does not provide the observer class {}",
+            class_name
+        )));
+    }
+    let class = class?;
+
+    env.register_native_methods(
+        &class,
+        &[NativeMethod {
+            name: "nativeOnChange".into(),
+            sig: "(J)V".into(),
+            fn_ptr: native_on_change as *mut c_void,
+        }],
+    )?;
+
+    let callback: Arc<WatchCallback> = Arc::new(Mutex::new(callback));
+    let callback = Arc::into_raw(callback) as jlong;
+    let registered = (|| -> Result<GlobalRef> {
+        let observer = env.new_object(&class, "(J)V", &[JValueGen::Long(callback)])?;
+        let content_resolver = content_resolver(env, context)?;
+        let result = env.call_method(
+            &content_resolver,
+            "registerContentObserver",
+            "(Landroid/net/Uri;ZLandroid/database/ContentObserver;)V",
+            &[
+                JValueGen::Object(uri),
+                JValueGen::Bool(1),
+                JValueGen::Object(&observer),
+            ],
+        );
+        check_exception(env)?;
+        result?;
+        Ok(env.new_global_ref(observer)?)
+    })();
+
+    match registered {
+        Ok(observer) => Ok(WatchHandle { observer, callback }),
+        Err(e) => {
+            // SAFETY: the observer was never registered, nothing else holds the pointer
+            unsafe { drop(Arc::from_raw(callback as *const WatchCallback)) };
+            Err(e)
+        }
+    }
+}
+
+/// SafContentObserver.nativeOnChange, called from onChange with the handle passed to its
+/// constructor.
+extern "system" fn native_on_change(_env: JNIEnv, _observer: JObject, handle: jlong) {
+    if handle == 0 {
+        return;
+    }
+    // SAFETY: the handle is an Arc<WatchCallback> from watch_uri; it is alive because the
+    // observer is only closed, and the Arc only released, after this call returned. Taking a
+    // reference of our own keeps it alive even if the callback drops its WatchHandle.
+    let callback = unsafe {
+        Arc::increment_strong_count(handle as *const WatchCallback);
+        Arc::from_raw(handle as *const WatchCallback)
+    };
+
+    // Unwinding into the JVM is undefined behavior
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let callback = callback.lock().unwrap_or_else(|e| e.into_inner());
+        callback();
+    }));
+    if result.is_err() {
+        info!("The ContentObserver callback panicked");
+    }
+}
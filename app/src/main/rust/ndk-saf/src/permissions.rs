@@ -0,0 +1,367 @@
+use std::{collections::HashSet, time::Duration};
+
+use crate::error::{Result, SafError};
+use jni::{
+    objects::{GlobalRef, JObject, JValueGen},
+    JNIEnv,
+};
+use log::info;
+
+use crate::jni_utils::{check_exception, get_application_context, get_env};
+use crate::ndk_saf::{content_resolver, object_to_string, parse_uri, sdk_int};
+use crate::observer::ObserverHandle;
+
+// Intent.FLAG_GRANT_READ_URI_PERMISSION
+pub(crate) const FLAG_GRANT_READ_URI_PERMISSION: i32 = 0x1;
+// Intent.FLAG_GRANT_WRITE_URI_PERMISSION
+pub(crate) const FLAG_GRANT_WRITE_URI_PERMISSION: i32 = 0x2;
+// PackageManager.PERMISSION_GRANTED
+const PERMISSION_GRANTED: i32 = 0;
+// Maximum number of persisted URI permissions per app, raised from 128 in Android 11 (API 30)
+const MAX_PERSISTED_PERMISSIONS: usize = 512;
+const MAX_PERSISTED_PERMISSIONS_PRE_R: usize = 128;
+// Interval between two checks of the persisted permissions in watch_permissions
+const PERMISSION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// Access the app holds on a URI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionLevel {
+    None,      // No access, e.g. the grant was revoked
+    Read,      // Read-only access
+    ReadWrite, // Read and write access
+}
+
+// A URI permission the app holds across restarts, see ContentResolver.getPersistedUriPermissions
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PersistedPermission {
+    pub url: String,         // URI the permission was granted on
+    pub read: bool,          // Read access is persisted
+    pub write: bool,         // Write access is persisted
+    pub persisted_time: i64, // Milliseconds since epoch when the permission was persisted
+}
+
+/// Get the tree URI a document URI belongs to, built with DocumentsContract.buildTreeDocumentUri.
+/// URIs that are not tree URIs are returned unchanged.
+pub(crate) fn owning_tree_uri<'local>(
+    env: &mut JNIEnv<'local>,
+    uri: &JObject,
+) -> Result<JObject<'local>> {
+    let documents_contract_class = "android/provider/DocumentsContract";
+    let is_tree_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "isTreeUri",
+            "(Landroid/net/Uri;)Z",
+            &[JValueGen::Object(uri)],
+        )?
+        .z()?;
+    if !is_tree_uri {
+        return Ok(env.new_local_ref(uri)?);
+    }
+
+    let authority = env
+        .call_method(uri, "getAuthority", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let tree_document_id = env
+        .call_static_method(
+            documents_contract_class,
+            "getTreeDocumentId",
+            "(Landroid/net/Uri;)Ljava/lang/String;",
+            &[JValueGen::Object(uri)],
+        )?
+        .l()?;
+    Ok(env
+        .call_static_method(
+            documents_contract_class,
+            "buildTreeDocumentUri",
+            "(Ljava/lang/String;Ljava/lang/String;)Landroid/net/Uri;",
+            &[
+                JValueGen::Object(&authority),
+                JValueGen::Object(&tree_document_id),
+            ],
+        )?
+        .l()?)
+}
+
+/// Check whether the current process holds the permissions in "mode_flags" (a combination of
+/// FLAG_GRANT_*_URI_PERMISSION) on the URI, with Context.checkUriPermission.
+pub(crate) fn check_uri_permission(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    uri: &JObject,
+    mode_flags: i32,
+) -> Result<bool> {
+    let pid = env
+        .call_static_method("android/os/Process", "myPid", "()I", &[])?
+        .i()?;
+    let uid = env
+        .call_static_method("android/os/Process", "myUid", "()I", &[])?
+        .i()?;
+    let result = env
+        .call_method(
+            context.as_obj(),
+            "checkUriPermission",
+            "(Landroid/net/Uri;III)I",
+            &[
+                JValueGen::Object(uri),
+                JValueGen::Int(pid),
+                JValueGen::Int(uid),
+                JValueGen::Int(mode_flags),
+            ],
+        )?
+        .i()?;
+    Ok(result == PERMISSION_GRANTED)
+}
+
+/// Determine the PermissionLevel the current process holds on the URI.
+pub(crate) fn permission_level_of(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    uri: &JObject,
+) -> Result<PermissionLevel> {
+    if !check_uri_permission(env, context, uri, FLAG_GRANT_READ_URI_PERMISSION)? {
+        return Ok(PermissionLevel::None);
+    }
+    if check_uri_permission(env, context, uri, FLAG_GRANT_WRITE_URI_PERMISSION)? {
+        Ok(PermissionLevel::ReadWrite)
+    } else {
+        Ok(PermissionLevel::Read)
+    }
+}
+
+/// Get all persisted URI permissions of the app, sorted by URI.
+pub(crate) fn query_persisted_permissions(
+    env: &mut JNIEnv,
+    content_resolver: &JObject,
+) -> Result<Vec<PersistedPermission>> {
+    let permissions = env
+        .call_method(
+            content_resolver,
+            "getPersistedUriPermissions",
+            "()Ljava/util/List;",
+            &[],
+        )?
+        .l()?;
+    let count = env.call_method(&permissions, "size", "()I", &[])?.i()?;
+    let mut persisted = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        let permission = env.with_local_frame(4, |env| -> Result<PersistedPermission> {
+            let permission = env
+                .call_method(
+                    &permissions,
+                    "get",
+                    "(I)Ljava/lang/Object;",
+                    &[JValueGen::Int(index)],
+                )?
+                .l()?;
+            let uri = env
+                .call_method(&permission, "getUri", "()Landroid/net/Uri;", &[])?
+                .l()?;
+            Ok(PersistedPermission {
+                url: object_to_string(env, &uri)?,
+                read: env
+                    .call_method(&permission, "isReadPermission", "()Z", &[])?
+                    .z()?,
+                write: env
+                    .call_method(&permission, "isWritePermission", "()Z", &[])?
+                    .z()?,
+                persisted_time: env
+                    .call_method(&permission, "getPersistedTime", "()J", &[])?
+                    .j()?,
+            })
+        })?;
+        persisted.push(permission);
+    }
+    persisted.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(persisted)
+}
+
+/// Persist the URI permissions granted for several URIs (e.g. folders picked with
+/// ACTION_OPEN_DOCUMENT_TREE) with ContentResolver.takePersistableUriPermission, so they survive
+/// app restarts. Read permission is always persisted, write permission too if "writable" is true.
+/// Before persisting, the number of persisted permissions is checked against the platform cap
+/// (128, or 512 since Android 11); URIs that would exceed it fail with an error instead of
+/// silently evicting older grants. URIs that are already persisted do not count towards the cap.
+/// <br />
+/// RETURNS: One entry per input URL, in input order, pairing the URL with the result of
+/// persisting it. A failure for one URL does not affect the others. <br />
+pub fn persist_permissions(urls: &[&str], writable: bool) -> Vec<(String, Result<()>)> {
+    info!("Persisting permissions of {} URLs", urls.len());
+
+    match persist_each(urls, writable) {
+        Ok(results) => results,
+        // Without an environment nothing can be persisted, report the cause for every URL
+        Err(e) => {
+            let message = e.to_string();
+            urls.iter()
+                .map(|url| (url.to_string(), Err(SafError::Other(message.clone()))))
+                .collect()
+        }
+    }
+}
+
+/// Persist the permissions of every URL with one JNIEnv and ContentResolver, see
+/// `persist_permissions`. Only errors in the shared setup are returned as a whole.
+fn persist_each(urls: &[&str], writable: bool) -> Result<Vec<(String, Result<()>)>> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+    let content_resolver = content_resolver(env, &context)?;
+
+    let mut persisted: HashSet<String> = query_persisted_permissions(env, &content_resolver)?
+        .into_iter()
+        .map(|permission| permission.url)
+        .collect();
+    let cap = if sdk_int(env)? >= 30 {
+        MAX_PERSISTED_PERMISSIONS
+    } else {
+        MAX_PERSISTED_PERMISSIONS_PRE_R
+    };
+    let mode_flags = if writable {
+        FLAG_GRANT_READ_URI_PERMISSION | FLAG_GRANT_WRITE_URI_PERMISSION
+    } else {
+        FLAG_GRANT_READ_URI_PERMISSION
+    };
+
+    let mut results = Vec::with_capacity(urls.len());
+    for url in urls {
+        let result = env.with_local_frame(8, |env| -> Result<()> {
+            let uri = parse_uri(env, url)?;
+            let uri_string = object_to_string(env, &uri)?;
+            if !persisted.contains(&uri_string) && persisted.len() >= cap {
+                return Err(SafError::Other(format!(
+                    "Cannot persist {}: the limit of {} persisted permissions is reached",
+                    url, cap
+                )));
+            }
+
+            let taken = env.call_method(
+                &content_resolver,
+                "takePersistableUriPermission",
+                "(Landroid/net/Uri;I)V",
+                &[JValueGen::Object(&uri), JValueGen::Int(mode_flags)],
+            );
+            if taken.is_err() {
+                // SecurityException when no persistable grant exists for the URI
+                env.exception_clear()?;
+                return Err(SafError::PermissionDenied(format!(
+                    "No persistable permission was granted for {}",
+                    url
+                )));
+            }
+            persisted.insert(uri_string);
+            Ok(())
+        });
+        results.push((url.to_string(), result));
+    }
+    Ok(results)
+}
+
+/// Persist the URI permission granted for a single URI, see `persist_permissions`. <br />
+/// PARAMS: "writable" also persists write access, read access is always persisted. <br />
+pub fn take_persistable_permission(url: &str, writable: bool) -> Result<()> {
+    persist_permissions(&[url], writable)
+        .pop()
+        .map_or(Ok(()), |(_, result)| result)
+}
+
+/// Release the persisted URI permission of a URI with
+/// ContentResolver.releasePersistableUriPermission, giving back every persisted access mode. The
+/// app keeps any access it was granted for the current process only. <br />
+/// RETURNS: A NotFound error if no permission is persisted for the URI. <br />
+pub fn release_persistable_permission(url: &str) -> Result<()> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    env.with_local_frame(8, |env| -> Result<()> {
+        let content_resolver = content_resolver(env, &context)?;
+        let uri = parse_uri(env, url)?;
+        let uri_string = object_to_string(env, &uri)?;
+        let permission = query_persisted_permissions(env, &content_resolver)?
+            .into_iter()
+            .find(|permission| permission.url == uri_string)
+            .ok_or_else(|| SafError::NotFound(format!("No permission is persisted for {}", url)))?;
+
+        let mut mode_flags = 0;
+        if permission.read {
+            mode_flags |= FLAG_GRANT_READ_URI_PERMISSION;
+        }
+        if permission.write {
+            mode_flags |= FLAG_GRANT_WRITE_URI_PERMISSION;
+        }
+        let released = env.call_method(
+            &content_resolver,
+            "releasePersistableUriPermission",
+            "(Landroid/net/Uri;I)V",
+            &[JValueGen::Object(&uri), JValueGen::Int(mode_flags)],
+        );
+        check_exception(env)?;
+        released?;
+        info!("Released persisted permission of {}", url);
+        Ok(())
+    })
+}
+
+/// Get the URIs of all persisted URI permissions of the app, sorted. Use this on startup to find
+/// out which stored URIs are still accessible. <br />
+pub fn persisted_permissions() -> Result<Vec<String>> {
+    // Obtain JNIEnv using improved get_env function
+    let mut env_guard = get_env()?;
+    let env = &mut *env_guard;
+    let context = get_application_context()?;
+
+    env.with_local_frame(16, |env| -> Result<Vec<String>> {
+        let content_resolver = content_resolver(env, &context)?;
+        Ok(query_persisted_permissions(env, &content_resolver)?
+            .into_iter()
+            .map(|permission| permission.url)
+            .collect())
+    })
+}
+
+/// Watch the persisted URI permissions of the app and call "callback" with the full current set
+/// whenever it changes, e.g. when the user revokes access to a folder in the system settings or
+/// the app releases a grant. Android has no change notification for persisted permissions, so
+/// they are polled every 2 seconds on a background thread attached to the JVM; the initial set
+/// is not reported. The callback runs on that thread. <br />
+/// RETURNS: A handle that keeps the watch running until it is dropped or stopped. <br />
+pub fn watch_permissions(
+    callback: Box<dyn Fn(Vec<PersistedPermission>) + Send>,
+) -> Result<ObserverHandle> {
+    let mut last = {
+        // Obtain JNIEnv using improved get_env function
+        let mut env_guard = get_env()?;
+        let env = &mut *env_guard;
+        let context = get_application_context()?;
+        let content_resolver = content_resolver(env, &context)?;
+        query_persisted_permissions(env, &content_resolver)?
+    };
+
+    Ok(ObserverHandle::polling(
+        PERMISSION_POLL_INTERVAL,
+        move || {
+            let current = (|| -> Result<Vec<PersistedPermission>> {
+                let mut env_guard = get_env()?;
+                let env = &mut *env_guard;
+                let context = get_application_context()?;
+                env.with_local_frame(16, |env| {
+                    let content_resolver = content_resolver(env, &context)?;
+                    query_persisted_permissions(env, &content_resolver)
+                })
+            })();
+            match current {
+                Ok(current) if current != last => {
+                    last = current.clone();
+                    callback(current);
+                }
+                Ok(_) => {}
+                // Keep watching, the next poll may succeed
+                Err(e) => info!("Failed to query persisted permissions: {}", e),
+            }
+            true
+        },
+    ))
+}
@@ -0,0 +1,234 @@
+use crate::error::Result;
+use jni::{
+    objects::{GlobalRef, JObject, JValueGen},
+    JNIEnv,
+};
+
+use crate::ndk_saf::{content_resolver, jstring_to_string};
+
+// Quota of the storage a document lives on, as reported by its provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaInfo {
+    pub used: u64,  // Bytes in use
+    pub total: u64, // Total capacity in bytes
+}
+
+// Space information of a provider root, DocumentsContract.Root columns
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RootSpace {
+    pub(crate) available: Option<u64>, // COLUMN_AVAILABLE_BYTES
+    pub(crate) capacity: Option<u64>,  // COLUMN_CAPACITY_BYTES, API 29+
+}
+
+/// Get the index of a cursor column by name, or None if the cursor has no such column.
+pub(crate) fn cursor_column_index(
+    env: &mut JNIEnv,
+    cursor: &JObject,
+    column: &str,
+) -> Result<Option<i32>> {
+    let column_name = env.new_string(column)?;
+    let index = env
+        .call_method(
+            cursor,
+            "getColumnIndex",
+            "(Ljava/lang/String;)I",
+            &[JValueGen::Object(&column_name)],
+        )?
+        .i()?;
+    Ok((index >= 0).then_some(index))
+}
+
+/// Read a long column of the current cursor row by name, or None if the column is absent or NULL.
+fn cursor_long_by_name(env: &mut JNIEnv, cursor: &JObject, column: &str) -> Result<Option<i64>> {
+    let Some(index) = cursor_column_index(env, cursor, column)? else {
+        return Ok(None);
+    };
+    let is_null = env
+        .call_method(cursor, "isNull", "(I)Z", &[JValueGen::Int(index)])?
+        .z()?;
+    if is_null {
+        return Ok(None);
+    }
+    Ok(Some(
+        env.call_method(cursor, "getLong", "(I)J", &[JValueGen::Int(index)])?
+            .j()?,
+    ))
+}
+
+/// Read a string column of the current cursor row by name, or None if absent or NULL.
+pub(crate) fn cursor_string_by_name(
+    env: &mut JNIEnv,
+    cursor: &JObject,
+    column: &str,
+) -> Result<Option<String>> {
+    let Some(index) = cursor_column_index(env, cursor, column)? else {
+        return Ok(None);
+    };
+    let value = env
+        .call_method(
+            cursor,
+            "getString",
+            "(I)Ljava/lang/String;",
+            &[JValueGen::Int(index)],
+        )?
+        .l()?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(jstring_to_string(env, value)?))
+}
+
+/// Query the roots of the provider owning "uri" and return the space information of the root
+/// the document belongs to. The root is the one whose document ID is the longest prefix of the
+/// document's tree (or document) ID; if none matches and the provider has a single root, that
+/// root is used. Returns None when no root can be matched or the provider cannot be queried.
+pub(crate) fn query_root_space(
+    env: &mut JNIEnv,
+    context: &GlobalRef,
+    uri: &JObject,
+) -> Result<Option<RootSpace>> {
+    let documents_contract_class = "android/provider/DocumentsContract";
+    let authority = env
+        .call_method(uri, "getAuthority", "()Ljava/lang/String;", &[])?
+        .l()?;
+    if authority.is_null() {
+        return Ok(None);
+    }
+    let is_tree_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "isTreeUri",
+            "(Landroid/net/Uri;)Z",
+            &[JValueGen::Object(uri)],
+        )?
+        .z()?;
+    let document_id = env
+        .call_static_method(
+            documents_contract_class,
+            if is_tree_uri {
+                "getTreeDocumentId"
+            } else {
+                "getDocumentId"
+            },
+            "(Landroid/net/Uri;)Ljava/lang/String;",
+            &[JValueGen::Object(uri)],
+        )?
+        .l()?;
+    let document_id = jstring_to_string(env, document_id)?;
+
+    let roots_uri = env
+        .call_static_method(
+            documents_contract_class,
+            "buildRootsUri",
+            "(Ljava/lang/String;)Landroid/net/Uri;",
+            &[JValueGen::Object(&authority)],
+        )?
+        .l()?;
+
+    // Query all columns, COLUMN_CAPACITY_BYTES is missing on older providers
+    let content_resolver = content_resolver(env, context)?;
+    let cursor = env.call_method(
+        &content_resolver,
+        "query",
+        "(Landroid/net/Uri;[Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;Ljava/lang/String;)Landroid/database/Cursor;",
+        &[
+            JValueGen::Object(&roots_uri),
+            JValueGen::Object(&JObject::null()),
+            JValueGen::Object(&JObject::null()),
+            JValueGen::Object(&JObject::null()),
+            JValueGen::Object(&JObject::null()),
+        ],
+    );
+    if cursor.is_err() {
+        // Providers may refuse root queries from apps other than the system UI
+        env.exception_clear()?;
+        return Ok(None);
+    }
+    let cursor = cursor?.l()?;
+    if cursor.is_null() {
+        return Ok(None);
+    }
+
+    let result = (|| -> Result<Option<RootSpace>> {
+        let mut roots = Vec::new();
+        while env.call_method(&cursor, "moveToNext", "()Z", &[])?.z()? {
+            let root_document_id = cursor_string_by_name(env, &cursor, "document_id")?;
+            let space = RootSpace {
+                available: cursor_long_by_name(env, &cursor, "available_bytes")?
+                    .and_then(|bytes| u64::try_from(bytes).ok()),
+                capacity: cursor_long_by_name(env, &cursor, "capacity_bytes")?
+                    .and_then(|bytes| u64::try_from(bytes).ok()),
+            };
+            roots.push((root_document_id.unwrap_or_default(), space));
+        }
+
+        let matching = roots
+            .iter()
+            .filter(|(root_id, _)| !root_id.is_empty() && document_id.starts_with(root_id))
+            .max_by_key(|(root_id, _)| root_id.len());
+        Ok(match (matching, roots.as_slice()) {
+            (Some((_, space)), _) => Some(*space),
+            (None, [(_, space)]) => Some(*space),
+            (None, _) => None,
+        })
+    })();
+
+    // Close the cursor
+    env.call_method(&cursor, "close", "()V", &[])?.v()?;
+    result
+}
+
+/// Get the space of the storage volume behind a document of the external storage provider with
+/// StatFs, for providers that do not report it in their roots. Document IDs of that provider
+/// start with the volume, e.g. "primary:Music" or "1234-5678:Music". <br />
+/// RETURNS: The space, or None for other providers or volumes that cannot be stat'ed. <br />
+pub(crate) fn query_volume_space(
+    env: &mut JNIEnv,
+    authority: &str,
+    document_id: &str,
+) -> Result<Option<RootSpace>> {
+    if authority != "com.android.externalstorage.documents" {
+        return Ok(None);
+    }
+    let Some((volume, _)) = document_id.split_once(':') else {
+        return Ok(None);
+    };
+
+    let volume_path = if volume == "primary" || volume == "home" {
+        let directory = env
+            .call_static_method(
+                "android/os/Environment",
+                "getExternalStorageDirectory",
+                "()Ljava/io/File;",
+                &[],
+            )?
+            .l()?;
+        env.call_method(&directory, "getAbsolutePath", "()Ljava/lang/String;", &[])?
+            .l()?
+    } else {
+        env.new_string(format!("/storage/{}", volume))?.into()
+    };
+
+    // StatFs throws IllegalArgumentException for paths it cannot stat
+    let stat_fs = env.new_object(
+        "android/os/StatFs",
+        "(Ljava/lang/String;)V",
+        &[JValueGen::Object(&volume_path)],
+    );
+    if stat_fs.is_err() {
+        env.exception_clear()?;
+        return Ok(None);
+    }
+    let stat_fs = stat_fs?;
+    let available = env
+        .call_method(&stat_fs, "getAvailableBytes", "()J", &[])?
+        .j()?;
+    let capacity = env
+        .call_method(&stat_fs, "getTotalBytes", "()J", &[])?
+        .j()?;
+
+    Ok(Some(RootSpace {
+        available: u64::try_from(available).ok(),
+        capacity: u64::try_from(capacity).ok(),
+    }))
+}
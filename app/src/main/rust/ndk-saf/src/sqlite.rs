@@ -0,0 +1,51 @@
+use std::{
+    fs::{File, OpenOptions},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Result, SafError};
+
+// A document that SQLite can open safely: a local file reachable by path, on a filesystem that
+// supports POSIX advisory locks
+#[derive(Debug)]
+pub struct SqliteHandle {
+    path: PathBuf,
+    // Keeps the file open while the handle lives, so the path stays valid
+    _file: File,
+}
+
+impl SqliteHandle {
+    /// Path to pass to SQLite (e.g. sqlite3_open_v2 or rusqlite::Connection::open).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Check whether the filesystem of the file supports POSIX record locks, which SQLite uses for
+/// its locking protocol, by asking fcntl(F_GETLK) about a whole-file write lock.
+fn supports_posix_locks(file: &File) -> bool {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = 0;
+    lock.l_len = 0;
+    unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) != -1 }
+}
+
+/// Open the local file at "path" for reading and writing and verify SQLite can use it.
+pub(crate) fn open_sqlite_path(path: PathBuf) -> Result<SqliteHandle> {
+    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+    if !file.metadata()?.file_type().is_file() {
+        return Err(SafError::Unsupported(
+            "The document is not a regular file".to_string(),
+        ));
+    }
+    if !supports_posix_locks(&file) {
+        return Err(SafError::Unsupported(
+            "The filesystem of the document does not support POSIX locks".to_string(),
+        ));
+    }
+
+    Ok(SqliteHandle { path, _file: file })
+}
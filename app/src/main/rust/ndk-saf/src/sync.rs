@@ -0,0 +1,166 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::error::{Result, SafError};
+
+use crate::file_io::copy_file_contents;
+use crate::ndk_saf::{AndroidFile, AndroidFileOps};
+
+// Progress callback of a sync, see `SyncOptions::progress`
+pub type SyncProgress = Box<dyn FnMut(&str) -> bool>;
+
+// Options of `AndroidFileOps::sync_into`
+#[derive(Default)]
+pub struct SyncOptions {
+    // Delete local files and directories that no longer exist in the source
+    pub delete_removed: bool,
+    // Called with the path (relative to the source) of every file before it is copied; returning
+    // false cancels the sync
+    pub progress: Option<SyncProgress>,
+}
+
+// Outcome of `AndroidFileOps::sync_into`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SyncReport {
+    pub added: usize,     // Files copied that did not exist locally
+    pub updated: usize,   // Files copied over an outdated local copy
+    pub deleted: usize,   // Local files and directories removed (with delete_removed)
+    pub unchanged: usize, // Files whose local copy was up to date
+}
+
+/// Whether the local file at "local" matches the source file by size and, if the provider
+/// reports it, modification time (which sync_directory copies to the local file).
+fn is_up_to_date(local: &Path, size: u64, last_modified: Option<i64>) -> bool {
+    let Ok(metadata) = fs::metadata(local) else {
+        return false;
+    };
+    if !metadata.is_file() || metadata.len() != size {
+        return false;
+    }
+    match (last_modified, metadata.modified()) {
+        (Some(millis), Ok(modified)) => {
+            modified
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis() as i64)
+                .unwrap_or(-1)
+                == millis
+        }
+        (None, _) => true,
+        (Some(_), Err(_)) => false,
+    }
+}
+
+/// Copy "source" to "local", writing a temporary file next to it first, so an interrupted copy
+/// never leaves a truncated file behind.
+fn copy_to_local(source: &AndroidFile, local: &Path, last_modified: Option<i64>) -> Result<()> {
+    let file_name = local
+        .file_name()
+        .ok_or_else(|| SafError::Other(format!("Invalid local path {}", local.display())))?;
+    let partial = local.with_file_name(format!(".{}.part", file_name.to_string_lossy()));
+
+    let mut destination = File::create(&partial)?;
+    let copied = copy_file_contents(&mut source.open("r")?, &mut destination)
+        .and_then(|_| match last_modified {
+            Some(millis) if millis > 0 => {
+                destination.set_modified(UNIX_EPOCH + Duration::from_millis(millis as u64))
+            }
+            _ => Ok(()),
+        })
+        .and_then(|_| destination.sync_all());
+    drop(destination);
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(e.into());
+    }
+
+    fs::rename(&partial, local)?;
+    Ok(())
+}
+
+/// Remove a local file or directory tree, returning the number of removed entries.
+fn remove_local(path: &Path) -> io::Result<usize> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(1)
+}
+
+/// Mirror the source directory "source" into "local", see `AndroidFileOps::sync_into`.
+pub(crate) fn sync_directory(
+    source: &AndroidFile,
+    local: &Path,
+    prefix: &str,
+    options: &mut SyncOptions,
+    report: &mut SyncReport,
+) -> Result<()> {
+    if fs::symlink_metadata(local).is_ok_and(|metadata| !metadata.is_dir()) {
+        report.deleted += remove_local(local)?;
+    }
+    fs::create_dir_all(local)?;
+
+    let mut names = HashSet::new();
+    for entry in source.list_files_detailed()? {
+        let name = entry.file.filename.clone();
+        let local_path = local.join(&name);
+        let relative_path = format!("{}{}", prefix, name);
+        names.insert(name);
+
+        if entry.file.is_dir {
+            sync_directory(
+                &entry.file,
+                &local_path,
+                &format!("{}/", relative_path),
+                options,
+                report,
+            )?;
+            continue;
+        }
+
+        if is_up_to_date(&local_path, entry.file.size, entry.file.last_modified) {
+            report.unchanged += 1;
+            continue;
+        }
+        if let Some(progress) = options.progress.as_mut() {
+            if !progress(&relative_path) {
+                return Err(SafError::Cancelled(format!(
+                    "Sync was cancelled at {}",
+                    relative_path
+                )));
+            }
+        }
+
+        let existed = match fs::symlink_metadata(&local_path) {
+            Ok(metadata) if metadata.is_dir() => {
+                report.deleted += remove_local(&local_path)?;
+                false
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        };
+        copy_to_local(&entry.file, &local_path, entry.file.last_modified)?;
+        if existed {
+            report.updated += 1;
+        } else {
+            report.added += 1;
+        }
+    }
+
+    if options.delete_removed {
+        for local_entry in fs::read_dir(local)? {
+            let local_entry = local_entry?;
+            let name = local_entry.file_name().to_string_lossy().into_owned();
+            if !names.contains(&name) {
+                report.deleted += remove_local(&local_entry.path())?;
+            }
+        }
+    }
+
+    Ok(())
+}
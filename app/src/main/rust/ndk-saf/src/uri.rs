@@ -0,0 +1,258 @@
+use std::fmt;
+
+use crate::error::{Result, SafError};
+
+const CONTENT_SCHEME: &str = "content://";
+const FILE_SCHEME: &str = "file:";
+
+// A content:// URI string, checked on construction. Its parts are read in Rust without parsing
+// it with android.net.Uri, following DocumentsContract for the document and tree IDs
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DocumentUri(String);
+
+impl DocumentUri {
+    /// Wrap a URI string after checking that it is a content:// URI with an authority. <br />
+    /// RETURNS: An error for other schemes (e.g. file://) or a missing authority. <br />
+    pub fn new(url: &str) -> Result<DocumentUri> {
+        let authority = url
+            .get(..CONTENT_SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(CONTENT_SCHEME))
+            .map(|_| authority_of(&url[CONTENT_SCHEME.len()..]));
+        match authority {
+            Some(authority) if !authority.is_empty() => Ok(DocumentUri(url.to_string())),
+            _ => Err(SafError::Other(format!("Not a content URI: {}", url))),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The authority of the provider serving the URI, e.g.
+    /// "com.android.externalstorage.documents".
+    pub fn authority(&self) -> &str {
+        authority_of(&self.0[CONTENT_SCHEME.len()..])
+    }
+
+    /// Whether the URI was built with DocumentsContract.buildTreeDocumentUri or
+    /// buildDocumentUriUsingTree, i.e. its path starts with "tree/<id>".
+    pub fn is_tree(&self) -> bool {
+        matches!(self.path_segments().as_slice(), ["tree", _, ..])
+    }
+
+    /// The decoded document ID (DocumentsContract.getDocumentId), e.g. "primary:Music/song.mp3".
+    /// RETURNS: None for URIs that do not point to a document, such as a bare tree URI. <br />
+    pub fn document_id(&self) -> Option<String> {
+        match self.path_segments().as_slice() {
+            ["document", id, ..] | ["tree", _, "document", id, ..] => Some(uri_decode(id)),
+            _ => None,
+        }
+    }
+
+    /// The decoded document ID of the tree root (DocumentsContract.getTreeDocumentId).
+    /// RETURNS: None if the URI is not a tree URI. <br />
+    pub fn tree_document_id(&self) -> Option<String> {
+        match self.path_segments().as_slice() {
+            ["tree", id, ..] => Some(uri_decode(id)),
+            _ => None,
+        }
+    }
+
+    /// The encoded, non-empty path segments, like Uri.getPathSegments without the decoding.
+    fn path_segments(&self) -> Vec<&str> {
+        let rest = &self.0[CONTENT_SCHEME.len()..];
+        let path = &rest[self.authority().len()..];
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+}
+
+impl fmt::Display for DocumentUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for DocumentUri {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Whether "url" is a file:// URI, e.g. of an object from `app_external_directory`, which cannot
+/// be queried through DocumentsContract.
+pub(crate) fn is_file_url(url: &str) -> bool {
+    url.get(..FILE_SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(FILE_SCHEME))
+}
+
+/// The authority at the start of "rest", the URI after its scheme.
+fn authority_of(rest: &str) -> &str {
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    &rest[..end]
+}
+
+/// Percent-encode a URI path segment the way android.net.Uri.encode does: every byte of the
+/// UTF-8 encoding except letters, digits and "_-!.~'()*" is escaped.
+pub(crate) fn uri_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"_-!.~'()*".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decode the percent escapes of a URI path segment, like android.net.Uri.decode. Invalid
+/// escapes are kept as they are, and invalid UTF-8 is replaced with U+FFFD.
+fn uri_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_encode_keeps_unreserved_characters() {
+        assert_eq!(uri_encode("AZaz09_-!.~'()*"), "AZaz09_-!.~'()*");
+    }
+
+    #[test]
+    fn uri_encode_escapes_reserved_characters() {
+        assert_eq!(uri_encode("primary:Music/a b"), "primary%3AMusic%2Fa%20b");
+        assert_eq!(uri_encode("100%"), "100%25");
+        assert_eq!(uri_encode("a+b&c=d?#"), "a%2Bb%26c%3Dd%3F%23");
+    }
+
+    #[test]
+    fn uri_encode_escapes_utf8_bytes() {
+        assert_eq!(uri_encode("é"), "%C3%A9");
+        assert_eq!(uri_encode("音楽"), "%E9%9F%B3%E6%A5%BD");
+    }
+
+    #[test]
+    fn uri_decode_reverses_uri_encode() {
+        for segment in ["primary:Music/a b", "100%", "音楽/é", "AZaz09_-!.~'()*"] {
+            assert_eq!(uri_decode(&uri_encode(segment)), segment);
+        }
+    }
+
+    #[test]
+    fn file_urls_are_recognized() {
+        assert!(is_file_url(
+            "file:///storage/emulated/0/Android/data/x/files"
+        ));
+        assert!(is_file_url("FILE:///sdcard"));
+        assert!(!is_file_url("content://provider/tree/root"));
+        assert!(!is_file_url("file"));
+    }
+
+    #[test]
+    fn tree_uris() {
+        let tree = DocumentUri::new(
+            "content://com.android.externalstorage.documents/tree/primary%3AMusic",
+        )
+        .unwrap();
+        assert_eq!(tree.authority(), "com.android.externalstorage.documents");
+        assert!(tree.is_tree());
+        assert_eq!(tree.tree_document_id().as_deref(), Some("primary:Music"));
+        assert_eq!(tree.document_id(), None);
+
+        let child = DocumentUri::new(
+            "content://com.android.externalstorage.documents/tree/primary%3AMusic/document/primary%3AMusic%2Fa%20b.mp3",
+        )
+        .unwrap();
+        assert!(child.is_tree());
+        assert_eq!(child.tree_document_id().as_deref(), Some("primary:Music"));
+        assert_eq!(
+            child.document_id().as_deref(),
+            Some("primary:Music/a b.mp3")
+        );
+    }
+
+    #[test]
+    fn document_uris() {
+        let document = DocumentUri::new(
+            "content://com.android.providers.media.documents/document/audio%3A42?x=1#frag",
+        )
+        .unwrap();
+        assert_eq!(
+            document.authority(),
+            "com.android.providers.media.documents"
+        );
+        assert!(!document.is_tree());
+        assert_eq!(document.document_id().as_deref(), Some("audio:42"));
+        assert_eq!(document.tree_document_id(), None);
+    }
+
+    #[test]
+    fn non_saf_content_uris() {
+        let media = DocumentUri::new("content://media/external/audio/media/42").unwrap();
+        assert_eq!(media.authority(), "media");
+        assert!(!media.is_tree());
+        assert_eq!(media.document_id(), None);
+        assert_eq!(media.tree_document_id(), None);
+
+        // The scheme is case-insensitive and the authority may have no path
+        let bare = DocumentUri::new("CONTENT://provider").unwrap();
+        assert_eq!(bare.authority(), "provider");
+        assert_eq!(bare.document_id(), None);
+    }
+
+    #[test]
+    fn encoded_document_ids_are_decoded() {
+        let uri = DocumentUri::new(&format!(
+            "content://provider/document/{}",
+            uri_encode("1F2E-3D4C:音楽/100% é.flac")
+        ))
+        .unwrap();
+        assert_eq!(
+            uri.document_id().as_deref(),
+            Some("1F2E-3D4C:音楽/100% é.flac")
+        );
+        // Invalid escapes are kept as they are
+        let uri = DocumentUri::new("content://provider/document/a%2xb%").unwrap();
+        assert_eq!(uri.document_id().as_deref(), Some("a%2xb%"));
+    }
+
+    #[test]
+    fn malformed_uris_are_rejected() {
+        for url in [
+            "",
+            "content://",
+            "content:///tree/primary%3A",
+            "content:",
+            "file:///sdcard/Music",
+            "https://example.com/tree/a",
+            "/storage/emulated/0",
+        ] {
+            assert!(DocumentUri::new(url).is_err(), "{url} was accepted");
+        }
+    }
+}